@@ -21,10 +21,7 @@ use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use log::LevelFilter;
 use redis::{Commands, RedisResult, IntoConnectionInfo, RedisError, Connection, ConnectionAddr, ConnectionInfo};
-
-use crate::task::Task;
-
-mod task;
+use scraper_task::Task;
 
 /// Create and return log4rs-config with some default values
 fn get_log4rs_config(log_path: &str, default_log_level: LevelFilter) -> log4rs::config::Config {
@@ -224,7 +221,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 Task::deserialise(msg.data).unwrap();
             let add_res: RedisResult<u32> = connection.sadd(
                 redis_set,
-                &received_task,
+                received_task.url.as_str(),
             );
             if let Ok(_) = add_res {
                 channel.basic_ack(msg.delivery_tag, false)
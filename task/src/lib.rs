@@ -0,0 +1,357 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::string::FromUtf8Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use url::Url;
+
+/// The version of the envelope written by `Task::serialise`. Newer versions may only add fields,
+/// such that modules can decode tasks written by modules that are newer than themselves.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Tasks are the workload instances assigned to Workers. It describes a single Url that needs
+/// to be resolved by the web scraper along with metadata about how it was found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub url: Url,
+    /// The Url of the page on which the Url was found. Seeds have no parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Url>,
+    /// The number of links followed from a seed to reach the Url. Seeds have depth 0.
+    #[serde(default)]
+    pub depth: u32,
+    /// When the Url was found as unix time in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovered: Option<i64>,
+    /// The number of times resolving the task has been attempted before
+    #[serde(default)]
+    pub attempts: u32,
+    /// How important it is to resolve the task soon. Higher is more important.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+    /// The crawl job the task belongs to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+    /// What a sitemap states about the page, if the Url was found in a sitemap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sitemap: Option<SitemapInfo>,
+    /// Fields written by newer versions. They are kept, such that they survive passing through
+    /// modules that do not know them yet.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+    /// The version of the envelope the task was received in. Tasks are serialised with this
+    /// version if it is newer than `ENVELOPE_VERSION`, as they may hold fields of that version.
+    #[serde(skip)]
+    pub version: u32,
+}
+
+/// How often a page is likely to change, as stated by a sitemap
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeFrequency {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl ChangeFrequency {
+    /// Parse a changefreq value of a sitemap. Returns None if the value is not valid.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "always" => Some(ChangeFrequency::Always),
+            "hourly" => Some(ChangeFrequency::Hourly),
+            "daily" => Some(ChangeFrequency::Daily),
+            "weekly" => Some(ChangeFrequency::Weekly),
+            "monthly" => Some(ChangeFrequency::Monthly),
+            "yearly" => Some(ChangeFrequency::Yearly),
+            "never" => Some(ChangeFrequency::Never),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeFrequency::Always => "always",
+            ChangeFrequency::Hourly => "hourly",
+            ChangeFrequency::Daily => "daily",
+            ChangeFrequency::Weekly => "weekly",
+            ChangeFrequency::Monthly => "monthly",
+            ChangeFrequency::Yearly => "yearly",
+            ChangeFrequency::Never => "never",
+        }
+    }
+}
+
+/// The optional lastmod, changefreq, and priority of a Url in a sitemap, which may be used to
+/// schedule the task
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SitemapInfo {
+    /// The date of last modification in W3C Datetime format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lastmod: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changefreq: Option<ChangeFrequency>,
+    /// The priority relative to other Urls of the site, between 0.0 and 1.0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<f32>,
+}
+
+/// The envelope of a serialised Task, i.e. the task and the version of the envelope
+#[derive(Serialize)]
+struct EnvelopeRef<'a> {
+    v: u32,
+    #[serde(flatten)]
+    task: &'a Task,
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    v: u32,
+    #[serde(flatten)]
+    task: Task,
+}
+
+impl Task {
+    /// Construct a new Task for the given Url with no metadata, e.g. a seed
+    pub fn new(url: Url) -> Self {
+        Task {
+            url,
+            parent: None,
+            depth: 0,
+            discovered: None,
+            attempts: 0,
+            priority: None,
+            job: None,
+            sitemap: None,
+            extra: Map::new(),
+            version: ENVELOPE_VERSION,
+        }
+    }
+
+    /// Construct a new Task for a Url found when resolving this task. The new task is one level
+    /// deeper than this task and belongs to the same job.
+    pub fn child(&self, url: Url) -> Self {
+        Task {
+            parent: Some(self.url.clone()),
+            depth: self.depth + 1,
+            discovered: Some(get_timestamp_millis()),
+            job: self.job.clone(),
+            ..Task::new(url)
+        }
+    }
+
+    /// Serialise the Task into a JSON envelope which makes it easier to transfer. The envelope
+    /// keeps the version the task was received in, unless that is older than `ENVELOPE_VERSION`.
+    pub fn serialise(&self) -> Vec<u8> {
+        serde_json::to_vec(&EnvelopeRef { v: self.version.max(ENVELOPE_VERSION), task: self })
+            .expect("Failed to serialise task")
+    }
+
+    /// Deserialise a series of bytes into a Task. Both envelopes and bare Urls, as written before
+    /// the envelope was introduced, are accepted.
+    pub fn deserialise(data: Vec<u8>) -> Result<Self, DecodeError> {
+        if data.first() == Some(&b'{') {
+            let envelope: Envelope = serde_json::from_slice(&data).map_err(DecodeError::InvalidEnvelope)?;
+            // Versions only add fields, so the version is not needed to decode the task
+            return Ok(Task {
+                version: envelope.v,
+                ..envelope.task
+            });
+        }
+
+        let data = String::from_utf8(data).map_err(DecodeError::InvalidUtf8)?;
+        let url = Url::parse(&data).map_err(DecodeError::InvalidUrl)?;
+        Ok(Task::new(url))
+    }
+}
+
+impl PartialEq for Task {
+    // Two tasks are equal if they have the same Url
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+    }
+}
+
+impl Eq for Task {}
+
+impl Hash for Task {
+    // Equal tasks must have equal hashes, so only the Url is hashed
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.url.hash(state)
+    }
+}
+
+/// Describes why a series of bytes could not be deserialised into a Task
+#[derive(Debug)]
+pub enum DecodeError {
+    InvalidEnvelope(serde_json::Error),
+    InvalidUtf8(FromUtf8Error),
+    InvalidUrl(url::ParseError),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::InvalidEnvelope(e) => write!(f, "Invalid task envelope: {}", e),
+            DecodeError::InvalidUtf8(e) => write!(f, "Task is not valid UTF-8: {}", e),
+            DecodeError::InvalidUrl(e) => write!(f, "Task is not a valid url: {}", e),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::InvalidEnvelope(e) => Some(e),
+            DecodeError::InvalidUtf8(e) => Some(e),
+            DecodeError::InvalidUrl(e) => Some(e),
+        }
+    }
+}
+
+/// Get current unix timestamp in milliseconds, or zero if the clock is before the epoch
+fn get_timestamp_millis() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => time.as_millis() as i64,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use url::Url;
+
+    use crate::{ChangeFrequency, SitemapInfo, Task};
+
+    fn task(url: &str) -> Task {
+        Task::new(Url::parse(url).unwrap())
+    }
+
+    /// Test if serialisation and deserialisation does not change the Task
+    #[test]
+    fn serialise_deserialise_success() {
+        let mut task1 = task("http://aau.dk/");
+        task1.depth = 2;
+        task1.attempts = 1;
+        task1.priority = Some(5);
+        task1.job = Some(String::from("job-1"));
+        task1.sitemap = Some(SitemapInfo {
+            lastmod: Some(String::from("2019-11-20")),
+            changefreq: Some(ChangeFrequency::Weekly),
+            priority: Some(0.8),
+        });
+        let task1_regen = Task::deserialise(task1.serialise()).unwrap();
+
+        assert_eq!(task1, task1_regen);
+        assert_eq!(task1_regen.depth, 2);
+        assert_eq!(task1_regen.attempts, 1);
+        assert_eq!(task1_regen.priority, Some(5));
+        assert_eq!(task1_regen.job, Some(String::from("job-1")));
+        assert_eq!(task1_regen.sitemap, task1.sitemap);
+    }
+
+    /// Test if serialisation and deserialisation on two different tasks still makes them unequal
+    #[test]
+    fn serialise_deserialise_failure() {
+        let task1_regen = Task::deserialise(task("http://aau.dk/").serialise()).unwrap();
+        let task2_regen = Task::deserialise(task("http://aau2.dk/").serialise()).unwrap();
+        assert_ne!(task1_regen, task2_regen);
+    }
+
+    /// The envelope is JSON with a version
+    #[test]
+    fn serialise_envelope() {
+        let json: Value = serde_json::from_slice(&task("http://aau.dk/").serialise()).unwrap();
+        assert_eq!(json["v"], 1);
+        assert_eq!(json["url"], "http://aau.dk/");
+    }
+
+    /// Bare Urls are decoded as seeds
+    #[test]
+    fn deserialise_bare_url() {
+        let task = Task::deserialise("http://aau.dk/".as_bytes().to_vec()).unwrap();
+        assert_eq!(task.url.as_str(), "http://aau.dk/");
+        assert_eq!(task.depth, 0);
+        assert_eq!(task.parent, None);
+    }
+
+    /// Envelopes of newer versions are decoded, and their unknown fields survive serialisation
+    #[test]
+    fn deserialise_newer_version() {
+        let data = r#"{"v":2,"url":"http://aau.dk/","depth":1,"language":"da"}"#;
+        let task = Task::deserialise(data.as_bytes().to_vec()).unwrap();
+        assert_eq!(task.depth, 1);
+        assert_eq!(task.extra["language"], "da");
+
+        let json: Value = serde_json::from_slice(&task.serialise()).unwrap();
+        assert_eq!(json["language"], "da");
+        assert_eq!(json["v"], 2);
+    }
+
+    /// Envelopes of older versions are serialised with the current version
+    #[test]
+    fn serialise_older_version() {
+        let data = r#"{"v":0,"url":"http://aau.dk/"}"#;
+        let task = Task::deserialise(data.as_bytes().to_vec()).unwrap();
+        let json: Value = serde_json::from_slice(&task.serialise()).unwrap();
+        assert_eq!(json["v"], 1);
+    }
+
+    /// Children are one level deeper than their parent and belong to the same job
+    #[test]
+    fn child() {
+        let mut parent = task("http://aau.dk/");
+        parent.depth = 2;
+        parent.attempts = 3;
+        parent.job = Some(String::from("job-1"));
+
+        let child = parent.child(Url::parse("http://aau.dk/child").unwrap());
+        assert_eq!(child.url.as_str(), "http://aau.dk/child");
+        assert_eq!(child.parent, Some(parent.url.clone()));
+        assert_eq!(child.depth, 3);
+        assert_eq!(child.attempts, 0);
+        assert_eq!(child.job, Some(String::from("job-1")));
+        assert!(child.discovered.is_some());
+    }
+
+    /// Equality between all-caps and all lower-caps
+    #[test]
+    fn normalisation_equality() {
+        assert_eq!(task("http://aau.dk"), task("HTTP://AAU.DK/"));
+        assert_eq!(task("http://aau.dk"), task("http://aau.dk:80"));
+    }
+
+    /// Domain labels and ports change semantics and as such these Urls are inequal
+    #[test]
+    fn normalisation_inequality() {
+        assert_ne!(task("https://aau.dk"), task("https://www.aau.dk"));
+        assert_ne!(task("http://aau.dk"), task("https://aau.dk:81"));
+    }
+
+    // deserialise casts an error when data is not an url
+    #[test]
+    fn deserialise_fail_01() {
+        assert!(Task::deserialise("mail@aau.dk".as_bytes().to_vec()).is_err());
+    }
+
+    // deserialise casts an error if the url contains anything that is not utf-8
+    #[test]
+    fn deserialise_fail_02() {
+        assert!(Task::deserialise(vec![b'h', b't', 0xff, 0xfe]).is_err());
+    }
+
+    // deserialise casts an error when the envelope is malformed or has no url
+    #[test]
+    fn deserialise_fail_03() {
+        assert!(Task::deserialise(r#"{"v":1,"url":"#.as_bytes().to_vec()).is_err());
+        assert!(Task::deserialise(r#"{"v":1,"depth":1}"#.as_bytes().to_vec()).is_err());
+    }
+}
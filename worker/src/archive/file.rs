@@ -0,0 +1,72 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::errors::{ArchiveError, ArchiveResult};
+use crate::errors::ArchiveErrorKind::{InvalidData, ServerError};
+use crate::traits::Archive;
+
+/// The FileArchive is an Archive that appends the found data to a file as JSON, one line per
+/// item, such that the file can be processed while the crawl is running
+pub struct FileArchive {
+    file: Mutex<File>,
+}
+
+impl FileArchive {
+    /// Construct a new FileArchive that appends to the file at the given path, which is created
+    /// if it does not exist
+    pub fn new(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileArchive {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl<D: Serialize> Archive<D> for FileArchive {
+    fn archive_content(&self, content: Vec<D>) -> ArchiveResult<()> {
+        if content.is_empty() {
+            return Ok(());
+        }
+
+        // The lines of the data are written at once, such that the data of concurrent tasks
+        // is not interleaved
+        let mut lines = vec![];
+        for item in content.iter() {
+            serde_json::to_writer(&mut lines, item)
+                .map_err(|e| ArchiveError::new(InvalidData, "Could not serialise data", Some(Box::new(e))))?;
+            lines.push(b'\n');
+        }
+
+        let mut file = self.file.lock().expect("Archive file mutex was corrupted");
+        // Failing to write, e.g. because the disk is full, may pass, so the task is retried
+        file.write_all(&lines)
+            .map_err(|e| ArchiveError::new(ServerError, "Could not write data to the archive file", Some(Box::new(e))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use crate::archive::file::FileArchive;
+    use crate::traits::Archive;
+
+    #[test]
+    fn archive_json_lines() {
+        let path = env::temp_dir().join(format!("scraper-archive-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let archive = FileArchive::new(path).unwrap();
+        archive.archive_content(vec![vec!["a"], vec!["b", "c"]]).unwrap();
+        archive.archive_content(Vec::<Vec<&str>>::new()).unwrap();
+        archive.archive_content(vec![vec!["d"]]).unwrap();
+
+        assert_eq!(fs::read_to_string(path).unwrap(), "[\"a\"]\n[\"b\",\"c\"]\n[\"d\"]\n");
+        fs::remove_file(path).unwrap();
+    }
+}
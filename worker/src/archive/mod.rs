@@ -1 +1,2 @@
+pub(crate) mod file;
 mod rmq;
\ No newline at end of file
@@ -0,0 +1,260 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::credentials::redact_url;
+use crate::defaultnormaliser::DefaultNormaliser;
+use crate::errors::{DownloadError, DownloadResult};
+use crate::errors::DownloadErrorKind::NotCached;
+use crate::recrawl::scheduler::content_hash;
+use crate::task::Task;
+use crate::traits::{Downloader, Response};
+
+/// How the CachingDownloader uses its cache
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    /// Pages in the cache are served from the cache, and other pages are downloaded and stored
+    Record,
+    /// Pages are only served from the cache, and nothing is downloaded
+    Replay,
+    /// Every page is downloaded and stored, replacing the page in the cache
+    Refresh,
+}
+
+/// What is stored about a response besides its page
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CachedResponse {
+    /// The normalised Url that the response is stored by
+    key: String,
+    url: String,
+    status: u16,
+    content_type: Option<String>,
+    charset: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+/// The CachingDownloader is a Downloader that stores every page downloaded by another Downloader
+/// on disk along with its headers, such that a crawl can be replayed without the network, e.g.
+/// to run changed extractors against the pages of an earlier crawl. Pages are stored by their
+/// normalised Url. Only successful responses are stored, so failed downloads are not replayed.
+///
+/// Each page is stored as two files in the cache directory, named by the hash of the normalised
+/// Url: the page itself (.body) and the rest of the response (.yaml).
+pub struct CachingDownloader {
+    downloader: Box<dyn Downloader<Vec<u8>>>,
+    directory: PathBuf,
+    mode: CacheMode,
+}
+
+impl CachingDownloader {
+    /// Construct a new CachingDownloader that caches the pages of the given Downloader in the
+    /// given directory, which is created if it does not exist
+    pub fn new(downloader: Box<dyn Downloader<Vec<u8>>>, directory: &str, mode: CacheMode) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        Ok(CachingDownloader {
+            downloader,
+            directory: PathBuf::from(directory),
+            mode,
+        })
+    }
+
+    /// The path of the files of the cached page of the key, without extension
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{:016x}", content_hash(&key)))
+    }
+
+    /// Read the cached page of the key, if it is in the cache
+    fn read(&self, key: &str) -> io::Result<Option<Response<Vec<u8>>>> {
+        let path = self.path(key);
+        let meta = match fs::read_to_string(path.with_extension("yaml")) {
+            Ok(meta) => meta,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let cached: CachedResponse = serde_yaml::from_str(&meta)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // Another Url with the same hash is not a hit
+        if cached.key != key {
+            return Ok(None);
+        }
+        let url = Url::parse(&cached.url).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let body = fs::read(path.with_extension("body"))?;
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in cached.headers {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+                headers.append(name, value);
+            }
+        }
+        Ok(Some(Response {
+            status: cached.status,
+            content_type: cached.content_type,
+            charset: cached.charset,
+            headers,
+            ..Response::new(url, body)
+        }))
+    }
+
+    /// Store the page of the response by the key. The page is written before the rest of the
+    /// response, and each file is moved into place once written, such that readers never see
+    /// a partial entry.
+    fn write(&self, key: &str, response: &Response<Vec<u8>>) -> io::Result<()> {
+        let cached = CachedResponse {
+            key: String::from(key),
+            url: response.url.to_string(),
+            status: response.status,
+            content_type: response.content_type.clone(),
+            charset: response.charset.clone(),
+            headers: response.headers.iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                .collect(),
+        };
+        let meta = serde_yaml::to_string(&cached).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let path = self.path(key);
+        write_atomically(&path.with_extension("body"), &response.body)?;
+        write_atomically(&path.with_extension("yaml"), meta.as_bytes())
+    }
+}
+
+impl Downloader<Vec<u8>> for CachingDownloader {
+    fn fetch_page(&self, task: &Task) -> DownloadResult<Response<Vec<u8>>> {
+        let key = DefaultNormaliser::full_normalisation(task.url.clone())
+            .unwrap_or_else(|_| task.url.clone())
+            .to_string();
+
+        if self.mode != CacheMode::Refresh {
+            match self.read(&key) {
+                Ok(Some(response)) => return Ok(response),
+                Ok(None) => {}
+                // Rather download the page again than fail the task
+                Err(e) => warn!("Failed to read the cached page of {}. {}", redact_url(&task.url), e),
+            }
+        }
+        if self.mode == CacheMode::Replay {
+            return Err(DownloadError::new(NotCached, "Page is not in the cache", None));
+        }
+
+        let response = self.downloader.fetch_page(task)?;
+        if let Err(e) = self.write(&key, &response) {
+            warn!("Failed to cache the page of {}. {}", redact_url(&task.url), e);
+        }
+        Ok(response)
+    }
+}
+
+/// The number of temporary files written by this process, which tells apart the temporary files
+/// of threads writing the same file
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Write the file through a temporary file that is moved into place
+fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let count = TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed);
+    let temporary = path.with_extension(format!("{}.{}.tmp", std::process::id(), count));
+    fs::write(&temporary, content)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    use mockito::mock;
+    use url::Url;
+
+    use crate::cache::{CacheMode, CachingDownloader};
+    use crate::downloader::{DefaultDownloader, DownloaderConfig};
+    use crate::errors::DownloadResult;
+    use crate::errors::DownloadErrorKind::NotCached;
+    use crate::extractor::html::{HTMLExtractorBase, HTMLLinkExtractor};
+    use crate::task::Task;
+    use crate::traits::{Downloader, Extractor, Response};
+
+    /// A Downloader that counts its downloads and serves the same page every time
+    struct CountingDownloader(Arc<Mutex<u32>>);
+
+    impl Downloader<Vec<u8>> for CountingDownloader {
+        fn fetch_page(&self, task: &Task) -> DownloadResult<Response<Vec<u8>>> {
+            *self.0.lock().unwrap() += 1;
+            let mut response = Response::new(task.url.clone(), b"<html>cached</html>".to_vec());
+            response.content_type = Some(String::from("text/html"));
+            response.headers.insert("etag", "\"abc\"".parse().unwrap());
+            Ok(response)
+        }
+    }
+
+    /// A CachingDownloader along with the number of downloads of the Downloader it wraps
+    fn downloader(directory: &str, mode: CacheMode) -> (CachingDownloader, Arc<Mutex<u32>>) {
+        let downloads = Arc::new(Mutex::new(0));
+        let inner = Box::new(CountingDownloader(downloads.clone()));
+        (CachingDownloader::new(inner, directory, mode).unwrap(), downloads)
+    }
+
+    /// Recorded pages are replayed without downloading, by their normalised Url
+    #[test]
+    fn record_and_replay() {
+        let directory = env::temp_dir().join(format!("scraper-cache-{}", std::process::id()));
+        let directory = directory.to_str().unwrap();
+        let task = Task::new(Url::parse("http://aau.dk/news?b=2&a=1").unwrap());
+
+        let (record, downloads) = downloader(directory, CacheMode::Record);
+        record.fetch_page(&task).unwrap();
+        record.fetch_page(&task).unwrap();
+        assert_eq!(*downloads.lock().unwrap(), 1);
+
+        let (replay, downloads) = downloader(directory, CacheMode::Replay);
+        let response = replay.fetch_page(&Task::new(Url::parse("http://AAU.dk/news?a=1&b=2").unwrap())).unwrap();
+        assert_eq!(response.body, b"<html>cached</html>".to_vec());
+        assert_eq!(response.content_type, Some(String::from("text/html")));
+        assert_eq!(response.headers["etag"], "\"abc\"");
+        assert_eq!(*downloads.lock().unwrap(), 0);
+        match replay.fetch_page(&Task::new(Url::parse("http://aau.dk/other").unwrap())).unwrap_err().kind {
+            NotCached => {}
+            _ => panic!(),
+        }
+
+        let (refresh, downloads) = downloader(directory, CacheMode::Refresh);
+        refresh.fetch_page(&task).unwrap();
+        assert_eq!(*downloads.lock().unwrap(), 1);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    /// A page downloaded in record mode is replayed without downloading it, and extracted like
+    /// the downloaded page, e.g. decoded by the charset of its headers
+    #[test]
+    fn replay_downloaded_page() {
+        let directory = env::temp_dir().join(format!("scraper-cache-replay-{}", std::process::id()));
+        let directory = directory.to_str().unwrap();
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/cached").unwrap();
+        let page = mock("GET", "/cached")
+            .with_status(200)
+            .with_header("content-type", "text/html; charset=windows-1251")
+            .with_body(&b"<a href=\"/\xe6\">\xe6</a>"[..])
+            .expect(1)
+            .create();
+        let downloader = |mode: CacheMode| {
+            let inner = Box::new(DefaultDownloader::new(&DownloaderConfig::default()));
+            CachingDownloader::new(inner, directory, mode).unwrap()
+        };
+
+        let recorded = downloader(CacheMode::Record).fetch_page(&Task::new(url.clone())).unwrap();
+        let replayed = downloader(CacheMode::Replay).fetch_page(&Task::new(url.clone())).unwrap();
+        page.assert();
+        assert_eq!(replayed.body, recorded.body);
+
+        let (links, _) = HTMLExtractorBase::new(HTMLLinkExtractor::new()).extract_content(replayed).unwrap();
+        let links: Vec<String> = links.into_iter().map(|link| link.url.to_string()).collect();
+        assert_eq!(links, vec![url.join("/ж").unwrap().to_string()]);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,53 +1,643 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
 use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use reqwest;
-use reqwest::Client;
+use reqwest::{Client, RedirectPolicy, RequestBuilder, StatusCode};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, LOCATION, RETRY_AFTER, USER_AGENT};
+use serde::Deserialize;
+use url::Url;
 
+use crate::credentials::{Credentials, redact_url};
 use crate::errors::{DownloadError, DownloadResult};
-use crate::errors::DownloadErrorKind::{InvalidPage, NetworkError};
+use crate::errors::DownloadErrorKind::{ClientError, InvalidPage, NetworkError, NotModified, Redirect, RejectedMediaType, ServerError};
+use crate::metrics::influx_client::get_timestamp_millis;
+use crate::proxypool::{ProxyConfig, ProxyConfigFile, ProxyPool};
+use crate::recrawl::validators::{RedisValidatorStore, Validators};
 use crate::task::Task;
-use crate::traits::Downloader;
+use crate::traits::{Downloader, Response};
+
+/// The media types that are downloaded by default, i.e. pages, sitemaps, and robots.txt. Many
+/// hosts serve pages as application/octet-stream, so those are downloaded too.
+pub const DEFAULT_ALLOWED_MEDIA_TYPES: &str =
+    "text/html,application/xhtml+xml,application/xml,text/xml,text/plain,application/gzip,application/x-gzip,application/octet-stream";
+
+/// The extensions of Urls that are likely to be binary files, and whose media type is checked
+/// before they are downloaded
+pub const DEFAULT_HEAD_EXTENSIONS: &str =
+    "7z,avi,bin,bmp,dmg,doc,docx,exe,flv,gif,ico,iso,jpeg,jpg,mkv,mov,mp3,mp4,mpeg,ogg,pdf,png,ppt,pptx,rar,tar,tif,tiff,wav,webm,webp,wmv,xls,xlsx,zip";
+
+/// The media types of Url extensions, which are checked without a request
+const EXTENSION_MEDIA_TYPES: &[(&str, &str)] = &[
+    ("7z", "application/x-7z-compressed"),
+    ("avi", "video/x-msvideo"),
+    ("bin", "application/octet-stream"),
+    ("bmp", "image/bmp"),
+    ("css", "text/css"),
+    ("dmg", "application/x-apple-diskimage"),
+    ("doc", "application/msword"),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("exe", "application/vnd.microsoft.portable-executable"),
+    ("flv", "video/x-flv"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("iso", "application/x-iso9660-image"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "application/javascript"),
+    ("json", "application/json"),
+    ("mkv", "video/x-matroska"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("mpeg", "video/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    ("pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+    ("rar", "application/vnd.rar"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webp", "image/webp"),
+    ("wmv", "video/x-ms-wmv"),
+    ("xls", "application/vnd.ms-excel"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("zip", "application/zip"),
+];
+
+/// Split a comma-separated list into its lower case entries
+pub fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|entry| entry.trim().to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// The DownloaderConfig describes how the DefaultDownloader makes requests
+#[derive(Debug, Clone)]
+pub struct DownloaderConfig {
+    pub user_agent: String,
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    pub max_redirects: usize,
+    /// The largest page in bytes that is downloaded
+    pub max_body_size: u64,
+    /// Headers sent with every request
+    pub headers: HashMap<String, String>,
+    /// Headers sent with requests to a host and its subdomains, which take precedence over the
+    /// headers sent with every request
+    pub host_headers: HashMap<String, HashMap<String, String>>,
+    /// The media types of the pages that are downloaded, e.g. "text/html" or "text/*". Pages of
+    /// other media types are rejected before their body is read. Every page is downloaded if empty.
+    pub allowed_media_types: Vec<String>,
+    /// The extensions of Urls whose media type is checked before downloading, e.g. "zip". The
+    /// media type of a known extension is checked without a request, and others with a HEAD request.
+    pub head_extensions: Vec<String>,
+    /// The proxies that requests are routed through
+    pub proxy: ProxyConfig,
+    /// Whether to keep the cookies set by hosts and send them back
+    pub cookies: bool,
+    /// The credentials of hosts, which apply to a host and its subdomains
+    pub credentials: HashMap<String, Credentials>,
+}
+
+impl Default for DownloaderConfig {
+    fn default() -> Self {
+        DownloaderConfig {
+            user_agent: String::from("DatScraper/0.1"),
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+            max_redirects: 10,
+            max_body_size: 10 * 1024 * 1024,
+            headers: HashMap::new(),
+            host_headers: HashMap::new(),
+            allowed_media_types: parse_list(DEFAULT_ALLOWED_MEDIA_TYPES),
+            head_extensions: parse_list(DEFAULT_HEAD_EXTENSIONS),
+            proxy: ProxyConfig::default(),
+            cookies: false,
+            credentials: HashMap::new(),
+        }
+    }
+}
+
+/// The content of a downloader config file. Every setting is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct DownloaderConfigFile {
+    user_agent: Option<String>,
+    timeout: Option<u64>,
+    connect_timeout: Option<u64>,
+    max_redirects: Option<usize>,
+    max_body_size: Option<u64>,
+    headers: HashMap<String, String>,
+    hosts: HashMap<String, HashMap<String, String>>,
+    allowed_media_types: Option<Vec<String>>,
+    head_extensions: Option<Vec<String>>,
+    proxy: Option<ProxyConfigFile>,
+}
+
+impl DownloaderConfig {
+    /// Apply the settings of a YAML config file. Settings in the file take precedence, and
+    /// headers in the file are added to the existing headers.
+    pub fn apply_yaml(&mut self, yaml: &str) -> Result<(), serde_yaml::Error> {
+        let file: DownloaderConfigFile = serde_yaml::from_str(yaml)?;
+
+        if let Some(user_agent) = file.user_agent {
+            self.user_agent = user_agent;
+        }
+        if let Some(timeout) = file.timeout {
+            self.timeout_secs = timeout;
+        }
+        if let Some(connect_timeout) = file.connect_timeout {
+            self.connect_timeout_secs = connect_timeout;
+        }
+        if let Some(max_redirects) = file.max_redirects {
+            self.max_redirects = max_redirects;
+        }
+        if let Some(max_body_size) = file.max_body_size {
+            self.max_body_size = max_body_size;
+        }
+        if let Some(allowed_media_types) = file.allowed_media_types {
+            self.allowed_media_types = allowed_media_types.iter().map(|media_type| media_type.to_lowercase()).collect();
+        }
+        if let Some(head_extensions) = file.head_extensions {
+            self.head_extensions = head_extensions.iter().map(|extension| extension.to_lowercase()).collect();
+        }
+        if let Some(proxy) = file.proxy {
+            self.proxy.apply_file(proxy);
+        }
+        self.headers.extend(file.headers);
+        for (host, headers) in file.hosts {
+            self.host_headers.entry(host.to_lowercase()).or_default().extend(headers);
+        }
+
+        Ok(())
+    }
+
+    /// Apply the settings of the YAML config file at the given path
+    pub fn apply_file(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let yaml = fs::read_to_string(path)?;
+        self.apply_yaml(&yaml)?;
+        Ok(())
+    }
+}
+
+/// Parse headers given by name and value. Invalid headers are a mistake in the configuration,
+/// so they cause a panic.
+fn parse_headers(headers: &HashMap<String, String>) -> HeaderMap {
+    headers.iter()
+        .map(|(name, value)| {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .unwrap_or_else(|_| panic!("Invalid header name: {:?}", name));
+            let value = HeaderValue::from_str(value)
+                .unwrap_or_else(|_| panic!("Invalid value of header {}: {:?}", name, value));
+            (name, value)
+        })
+        .collect()
+}
+
+/// Whether a host has been logged in to. It is locked while logging in to the host.
+type LoginState = Arc<Mutex<bool>>;
 
 /// A struct to access functions in downloader file
 /// Contains a reqwest client to send http requests
 pub(crate) struct DefaultDownloader {
-    client: reqwest::Client
+    client: reqwest::Client,
+    host_headers: HashMap<String, HeaderMap>,
+    max_body_size: u64,
+    allowed_media_types: Vec<String>,
+    head_extensions: Vec<String>,
+    proxies: Option<Arc<ProxyPool>>,
+    credentials: HashMap<String, Credentials>,
+    /// Whether each host with a login form has been logged in to through each proxy, as each
+    /// proxy has its own cookies. Each host has its own lock, such that it is logged in to once.
+    logins: Mutex<HashMap<(Option<usize>, String), LoginState>>,
+    validators: Option<RedisValidatorStore>,
 }
 
 impl DefaultDownloader {
-    pub fn new() -> Self {
-        DefaultDownloader { client: Client::new() }
+    /// Construct a new DefaultDownloader that makes requests as described by the config
+    pub fn new(config: &DownloaderConfig) -> Self {
+        // Login forms are of no use unless the session cookies are kept
+        let cookies = config.cookies || config.credentials.values().any(Credentials::needs_cookies);
+        // The headers and credentials of a host must not be sent to the host it redirects to
+        let private_hosts: HashSet<String> = config.host_headers.keys()
+            .chain(config.credentials.keys())
+            .map(|host| host.to_lowercase())
+            .collect();
+        let client_builder = || {
+            let mut headers = parse_headers(&config.headers);
+            headers.insert(USER_AGENT, HeaderValue::from_str(&config.user_agent).expect("Invalid user agent"));
+
+            Client::builder()
+                .default_headers(headers)
+                .timeout(Duration::from_secs(config.timeout_secs))
+                .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+                // Without redirects, the redirects are followed as new tasks instead
+                .redirect(if config.max_redirects == 0 {
+                    RedirectPolicy::none()
+                } else {
+                    redirect_policy(config.max_redirects, private_hosts.clone())
+                })
+                .cookie_store(cookies)
+        };
+
+        DefaultDownloader {
+            client: client_builder().build().expect("Failed to build downloader client"),
+            proxies: if config.proxy.urls.is_empty() {
+                None
+            } else {
+                Some(Arc::new(ProxyPool::new(&config.proxy, client_builder)))
+            },
+            host_headers: config.host_headers.iter()
+                .map(|(host, headers)| (host.to_lowercase(), parse_headers(headers)))
+                .collect(),
+            max_body_size: config.max_body_size,
+            allowed_media_types: config.allowed_media_types.clone(),
+            head_extensions: config.head_extensions.clone(),
+            credentials: config.credentials.iter()
+                .map(|(host, credentials)| (host.to_lowercase(), credentials.clone()))
+                .collect(),
+            logins: Mutex::new(HashMap::new()),
+            validators: None,
+        }
     }
-}
 
-impl Downloader<Vec<u8>> for DefaultDownloader {
-    /// Takes a task and returns either a vec<u8> with contents of the url in task, or an error.
-    /// If function is successful it will return a Vec<u8> with the page contents, otherwise Error
-    fn fetch_page(&self, task: &Task) -> DownloadResult<Vec<u8>> {
+    /// Construct a new DefaultDownloader that stores the ETag and Last-Modified validators of
+    /// each page, and sends them when the page is downloaded again. A page that has not been
+    /// modified since is not downloaded again, and a NotModified error is returned instead.
+    pub fn with_validators(config: &DownloaderConfig, validators: RedisValidatorStore) -> Self {
+        DefaultDownloader {
+            validators: Some(validators),
+            ..DefaultDownloader::new(config)
+        }
+    }
+
+    /// The pool of proxies that requests are routed through, if any
+    pub fn proxy_pool(&self) -> Option<Arc<ProxyPool>> {
+        self.proxies.clone()
+    }
+
+    /// The client of a request to the url along with the index of the proxy that it routes the
+    /// request through, if requests are routed through proxies
+    fn client(&self, url: &Url) -> DownloadResult<(&Client, Option<usize>)> {
+        match &self.proxies {
+            None => Ok((&self.client, None)),
+            Some(pool) => match pool.select(url) {
+                Some((index, client)) => Ok((client, Some(index))),
+                None => Err(DownloadError::new(NetworkError, "Every proxy is out of rotation", None)),
+            },
+        }
+    }
+
+    /// Send the request and report its outcome to the proxy pool. Failing to get a response, or
+    /// being refused by the proxy, counts against the proxy.
+    fn send(&self, request: RequestBuilder, proxy: Option<usize>) -> reqwest::Result<reqwest::Response> {
+        let result = request.send();
+        if let (Some(pool), Some(index)) = (&self.proxies, proxy) {
+            pool.report_response(index, &result);
+        }
+        result
+    }
+
+    /// Add the headers and credentials of the host of the url to the request. The headers of the
+    /// most specific host apply, i.e. the host itself before its parent domains. Basic and bearer
+    /// credentials are only sent to their exact host, and only over https.
+    fn apply_host_headers(&self, url: &Url, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some((_, headers)) = find_host(&self.host_headers, url) {
+            for (name, value) in headers.iter() {
+                request = request.header(name.clone(), value.clone());
+            }
+        }
+        match request_credentials(&self.credentials, url) {
+            Some(Credentials::Basic { username, password }) => request.basic_auth(username, password.as_ref()),
+            Some(Credentials::Bearer(token)) => request.bearer_auth(token),
+            _ => request,
+        }
+    }
+
+    /// The login state of the host of the url through the proxy, which is locked while logging in
+    fn login_state(&self, proxy: Option<usize>, host: &str) -> LoginState {
+        self.logins.lock().unwrap()
+            .entry((proxy, String::from(host)))
+            .or_default()
+            .clone()
+    }
+
+    /// Post the login form of the host of the url, unless it has been logged in to already
+    /// through the proxy. The session cookies set by the login are kept by the client. If
+    /// `renew` is true, the host is logged in to again, as its session has expired.
+    fn log_in(&self, client: &Client, proxy: Option<usize>, url: &Url, renew: bool) -> DownloadResult<()> {
+        let (host, login_url, fields) = match find_host(&self.credentials, url) {
+            Some((host, Credentials::Form { url, fields })) => (host, url, fields),
+            _ => return Ok(()),
+        };
+        // Other requests to the host wait for the login rather than logging in as well
+        let state = self.login_state(proxy, host);
+        let mut logged_in = state.lock().unwrap();
+        if *logged_in && !renew {
+            return Ok(());
+        }
+
+        match self.send(client.post(login_url.as_str()).form(fields), proxy) {
+            Ok(ref res) if res.status().is_success() || res.status().is_redirection() => {
+                info!("Logged in to {}", host);
+                *logged_in = true;
+                Ok(())
+            }
+            Ok(res) => Err(DownloadError::new(ClientError(res.status().as_u16()), "Failed to log in to host", None)),
+            Err(e) => Err(DownloadError::new(NetworkError, "Failed to log in to host", Some(Box::new(e)))),
+        }
+    }
+
+    /// Whether the response shows that the session of the login form of the host of the url has
+    /// expired, i.e. the request was refused or sent to the login form
+    fn session_expired(&self, url: &Url, result: &reqwest::Result<reqwest::Response>) -> bool {
+        let login_url = match find_host(&self.credentials, url) {
+            Some((_, Credentials::Form { url, .. })) => url,
+            _ => return false,
+        };
+        let res = match result {
+            Ok(res) => res,
+            Err(_) => return false,
+        };
+        let location = res.headers().get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|location| url.join(location).ok());
+        res.status() == StatusCode::UNAUTHORIZED
+            || res.status() == StatusCode::FORBIDDEN
+            || res.url().as_str() == login_url
+            || location.as_ref().map(Url::as_str) == Some(login_url.as_str())
+    }
+
+    /// Whether pages of the given media type are downloaded. Pages of an unknown media type are
+    /// downloaded, as they may still be pages.
+    fn is_allowed_media_type(&self, media_type: Option<&String>) -> bool {
+        let media_type = match media_type {
+            Some(media_type) => media_type,
+            None => return true,
+        };
+        self.allowed_media_types.is_empty() || self.allowed_media_types.iter().any(|allowed| {
+            allowed == "*/*" || allowed == media_type
+                || (allowed.ends_with("/*") && media_type.starts_with(&allowed[..allowed.len() - 1]))
+        })
+    }
+
+    /// Reject the page if its media type is not allowed
+    fn check_media_type(&self, media_type: Option<&String>) -> DownloadResult<()> {
+        if self.is_allowed_media_type(media_type) {
+            Ok(())
+        } else {
+            let media_type = media_type.cloned().unwrap_or_default();
+            Err(DownloadError::new(RejectedMediaType(media_type), "Page is not of an allowed media type", None))
+        }
+    }
+
+    /// Check the media type of the url if its extension suggests that it is a binary file, such
+    /// that it is not downloaded in vain. The media type is known from the extension if possible,
+    /// and otherwise found with a HEAD request. Hosts that do not answer HEAD requests are checked
+    /// when the page is downloaded instead.
+    fn check_head(&self, client: &Client, proxy: Option<usize>, url: &Url) -> DownloadResult<()> {
+        let file_name = url.path().rsplit('/').next().unwrap_or("");
+        let extension = match file_name.rfind('.') {
+            Some(index) => file_name[index + 1..].to_lowercase(),
+            None => return Ok(()),
+        };
+        if !self.head_extensions.contains(&extension) {
+            return Ok(());
+        }
+        if let Some((_, media_type)) = EXTENSION_MEDIA_TYPES.iter().find(|(known, _)| *known == extension) {
+            return self.check_media_type(Some(&String::from(*media_type)));
+        }
+
+        match self.send(self.apply_host_headers(url, client.head(url.as_str())), proxy) {
+            Ok(ref res) if res.status().is_success() => {
+                let (media_type, _) = res.headers().get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map_or((None, None), parse_content_type);
+                self.check_media_type(media_type.as_ref())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Download the page of the url, unless it has not been modified since the given validators
+    /// were received or it is not of an allowed media type
+    fn download(&self, url: &Url, validators: &Validators) -> DownloadResult<Response<Vec<u8>>> {
+        let (client, proxy) = self.client(url)?;
+        self.log_in(client, proxy, url, false)?;
+        self.check_head(client, proxy, url)?;
+
         // Attempts to get html from url using the client
-        match self.client.get(task.url.as_str()).send() {
-            Ok(mut res) => {
-                // Read html as bytes into vec
-                let mut body: Vec<u8> = Vec::new();
-                match res.read_to_end(&mut body) {
-                    // If successful return the vec with bytes
-                    Ok(_) => Ok(body),
+        let request = || validators.apply(self.apply_host_headers(url, client.get(url.as_str())));
+        let mut start = Instant::now();
+        let mut result = self.send(request(), proxy);
+        if self.session_expired(url, &result) {
+            // Log in again and repeat the request with the new session
+            info!("The session of {} has expired", redact_url(url));
+            self.log_in(client, proxy, url, true)?;
+            start = Instant::now();
+            result = self.send(request(), proxy);
+        }
+        match result {
+            Ok(ref res) if !res.status().is_success() => {
+                let now_secs = (get_timestamp_millis() / 1000) as u64;
+                Err(status_error(res.status(), res.headers(), now_secs))
+            }
+            Ok(res) => {
+                let response_time = start.elapsed();
+                let (content_type, charset) = res.headers().get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map_or((None, None), parse_content_type);
+                self.check_media_type(content_type.as_ref())?;
+
+                // The Url of reqwest is of another version of the url crate
+                let final_url = Url::parse(res.url().as_str()).unwrap_or_else(|_| url.clone());
+                let mut response = Response {
+                    status: res.status().as_u16(),
+                    content_type,
+                    charset,
+                    headers: res.headers().clone(),
+                    response_time,
+                    ..Response::new(final_url, Vec::new())
+                };
+
+                // Read html as bytes into vec, but never more than the maximum body size
+                match res.take(self.max_body_size.saturating_add(1)).read_to_end(&mut response.body) {
+                    Ok(_) if response.body.len() as u64 > self.max_body_size => {
+                        Err(DownloadError::new(InvalidPage, "Page is larger than the maximum body size", None))
+                    }
+                    // If successful return the response with the bytes
+                    Ok(_) => {
+                        response.download_time = start.elapsed();
+                        Ok(response)
+                    }
                     // Otherwise error
                     Err(e) => Err(DownloadError::new(InvalidPage, "Could not read downloaded page", Some(Box::new(e)))),
                 }
             }
+            Err(ref e) if e.is_redirect() => Err(DownloadError::new(InvalidPage, "Page redirects too many times", None)),
             Err(e) => Err(DownloadError::new(NetworkError, "Failed to download page", Some(Box::new(e)))),
         }
     }
 }
 
+/// The credentials that are sent with requests to the url. Login forms apply to their host and
+/// its subdomains, while basic and bearer credentials are only sent to their exact host over
+/// https, such that they are not sent in the clear or to another host.
+fn request_credentials<'a>(credentials: &'a HashMap<String, Credentials>, url: &Url) -> Option<&'a Credentials> {
+    match find_host(credentials, url) {
+        Some((_, form @ Credentials::Form { .. })) => Some(form),
+        _ if url.scheme() == "https" => credentials.get(url.host_str()?),
+        _ => None,
+    }
+}
+
+/// The policy of following at most `max_redirects` redirects. Redirects from or to another host
+/// are not followed if either host is one of the given private hosts or their subdomains, as the
+/// headers and credentials of the private host would be sent along. The redirect is then
+/// returned as a response, such that its target is submitted as a new task.
+fn redirect_policy(max_redirects: usize, private_hosts: HashSet<String>) -> RedirectPolicy {
+    let limited = RedirectPolicy::limited(max_redirects);
+    RedirectPolicy::custom(move |attempt| {
+        let from = attempt.previous().last().and_then(|url| url.host_str()).unwrap_or("");
+        let to = attempt.url().host_str().unwrap_or("");
+        let is_private = |host: &str| domains(host).any(|domain| private_hosts.contains(domain));
+        if from != to && (is_private(from) || is_private(to)) {
+            attempt.stop()
+        } else {
+            limited.redirect(attempt)
+        }
+    })
+}
+
+/// The domains of a host, i.e. the host itself followed by its parent domains
+fn domains(host: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(host), |domain| domain.find('.').map(|index| &domain[index + 1..]))
+}
+
+/// Find the entry of the most specific host of the url, i.e. the host itself before its parent
+/// domains, along with the host of the entry
+fn find_host<'a, 'u, T>(hosts: &'a HashMap<String, T>, url: &'u Url) -> Option<(&'u str, &'a T)> {
+    domains(url.host_str().unwrap_or("")).find_map(|domain| hosts.get(domain).map(|entry| (domain, entry)))
+}
+
+/// Parse the value of a Content-Type header into its media type and charset, both in lower case
+fn parse_content_type(value: &str) -> (Option<String>, Option<String>) {
+    let mut parts = value.split(';');
+    let media_type = parts.next()
+        .map(|media_type| media_type.trim().to_lowercase())
+        .filter(|media_type| !media_type.is_empty());
+    let charset = parts
+        .filter_map(|parameter| {
+            let mut pair = parameter.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("charset") => {
+                    Some(value.trim().trim_matches('"').to_lowercase())
+                }
+                _ => None,
+            }
+        })
+        .next()
+        .filter(|charset| !charset.is_empty());
+    (media_type, charset)
+}
+
+/// The error of a response that was not successful, given its status code and headers
+fn status_error(status: StatusCode, headers: &HeaderMap, now_secs: u64) -> DownloadError {
+    let code = status.as_u16();
+    if status == StatusCode::NOT_MODIFIED {
+        DownloadError::new(NotModified, "Page was not modified since it was last downloaded", None)
+    } else if status.is_redirection() {
+        let location = headers.get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        DownloadError::new(Redirect(code, location), "Page redirects elsewhere", None)
+    } else if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after_ms = headers.get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, now_secs));
+        DownloadError::new(ServerError(code, retry_after_ms), "Host failed to serve the page", None)
+    } else {
+        // Other client errors and unexpected status codes will not change when retried
+        DownloadError::new(ClientError(code), "Host refused to serve the page", None)
+    }
+}
+
+/// Parse the value of a Retry-After header, which is either a number of seconds or an HTTP
+/// date, into the number of milliseconds to wait from now
+fn parse_retry_after(value: &str, now_secs: u64) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds.saturating_mul(1000));
+    }
+    parse_http_date(value).map(|date| date.saturating_sub(now_secs).saturating_mul(1000))
+}
+
+/// Parse an HTTP date, e.g. "Wed, 21 Oct 2015 07:28:00 GMT", into seconds since the Unix epoch
+fn parse_http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    if parts.len() != 6 || parts[5] != "GMT" {
+        return None;
+    }
+    let day: u64 = parts[1].parse().ok()?;
+    let month = MONTHS.iter().position(|month| *month == parts[2])? as u64 + 1;
+    let year: u64 = parts[3].parse().ok()?;
+    let time: Vec<u64> = parts[4].split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    if time.len() != 3 || year < 1970 || day == 0 {
+        return None;
+    }
+
+    // Count the days since the epoch with years starting in March, such that leap days end the year
+    let (year, month) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+
+    Some(days * 86400 + time[0] * 3600 + time[1] * 60 + time[2])
+}
+
+impl Downloader<Vec<u8>> for DefaultDownloader {
+    /// Takes a task and returns either the response with the contents of the url in task, or an
+    /// error. If function is successful the body of the response is a Vec<u8> with the page contents
+    fn fetch_page(&self, task: &Task) -> DownloadResult<Response<Vec<u8>>> {
+        let store = match &self.validators {
+            Some(store) => store,
+            None => return self.download(&task.url, &Validators::default()),
+        };
+
+        // Rather download the page unconditionally than not at all
+        let validators = store.get(&task.url).unwrap_or_else(|e| {
+            warn!("Failed to get the validators of {}. {}", redact_url(&task.url), e);
+            Validators::default()
+        });
+        self.download(&task.url, &validators)
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
-    use mockito::mock;
+    use mockito::{Matcher, mock};
     use url::Url;
 
-    use crate::downloader::DefaultDownloader;
+    use crate::credentials::Secrets;
+    use crate::downloader::{DefaultDownloader, DownloaderConfig, parse_content_type, parse_http_date, parse_retry_after, request_credentials};
+    use crate::errors::DownloadErrorKind::{ClientError, NotModified, Redirect, RejectedMediaType, ServerError};
+    use crate::recrawl::validators::Validators;
     use crate::task::Task;
     use crate::traits::Downloader;
 
@@ -78,11 +668,374 @@ mod tests {
             .create();
 
         // Attempts to access the mock and downloads the body
-        let dl: DefaultDownloader = DefaultDownloader::new();
+        let dl: DefaultDownloader = DefaultDownloader::new(&DownloaderConfig::default());
         let data = dl.fetch_page(
-            &Task { url: Url::parse(&url).unwrap() });
+            &Task::new(Url::parse(&url).unwrap()));
 
         // Asserts the downloaded data, and expected value is the same
-        assert_eq!(data.unwrap(), expected);
+        let response = data.unwrap();
+        assert_eq!(response.body, expected);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.content_type, Some(String::from("text/plain")));
+    }
+
+    /// Validators are sent with the request, and a 304 response means the page is not modified
+    #[test]
+    fn test_downloader_not_modified() {
+        let _m = mock("GET", "/cached")
+            .match_header("if-none-match", "\"abc\"")
+            .match_header("if-modified-since", "Wed, 20 Nov 2019 10:00:00 GMT")
+            .with_status(304)
+            .create();
+
+        let validators = Validators {
+            etag: Some(String::from("\"abc\"")),
+            last_modified: Some(String::from("Wed, 20 Nov 2019 10:00:00 GMT")),
+        };
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/cached").unwrap();
+        let result = DefaultDownloader::new(&DownloaderConfig::default()).download(&url, &validators);
+
+        match result {
+            Err(e) => match e.kind {
+                NotModified => {}
+                _ => panic!(),
+            },
+            Ok(_) => panic!(),
+        }
+    }
+
+    /// The validators of a downloaded page are read from its headers
+    #[test]
+    fn test_downloader_validators() {
+        let _m = mock("GET", "/fresh")
+            .with_status(200)
+            .with_header("etag", "\"def\"")
+            .with_body("fresh")
+            .create();
+
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/fresh").unwrap();
+        let response = DefaultDownloader::new(&DownloaderConfig::default()).download(&url, &Validators::default()).unwrap();
+
+        assert_eq!(response.body, b"fresh".to_vec());
+        assert_eq!(Validators::from_headers(&response.headers), Validators { etag: Some(String::from("\"def\"")), last_modified: None });
+    }
+
+    /// The user agent, the headers, and the headers of the host are sent with the request
+    #[test]
+    fn test_downloader_headers() {
+        let _m = mock("GET", "/headers")
+            .match_header("user-agent", "TestBot/1.0")
+            .match_header("accept-language", "da")
+            .match_header("x-token", "secret")
+            .with_status(200)
+            .with_body("headers")
+            .create();
+
+        let mut config = DownloaderConfig::default();
+        config.apply_yaml("
+user-agent: TestBot/1.0
+headers:
+  Accept-Language: da
+hosts:
+  127.0.0.1:
+    X-Token: secret
+  example.com:
+    X-Token: other
+").unwrap();
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/headers").unwrap();
+        let data = DefaultDownloader::new(&config).fetch_page(&Task::new(url));
+
+        assert_eq!(data.unwrap().body, b"headers".to_vec());
+    }
+
+    /// Pages larger than the maximum body size are not downloaded
+    #[test]
+    fn test_downloader_max_body_size() {
+        let _m = mock("GET", "/large")
+            .with_status(200)
+            .with_body("0123456789")
+            .create();
+
+        let mut config = DownloaderConfig { max_body_size: 5, ..DownloaderConfig::default() };
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/large").unwrap();
+
+        assert!(DefaultDownloader::new(&config).fetch_page(&Task::new(url.clone())).is_err());
+        config.max_body_size = 10;
+        assert!(DefaultDownloader::new(&config).fetch_page(&Task::new(url)).is_ok());
+    }
+
+    /// Unknown settings are rejected rather than silently ignored
+    #[test]
+    fn test_downloader_config_unknown_setting() {
+        let mut config = DownloaderConfig::default();
+        assert!(config.apply_yaml("user-agnet: TestBot/1.0").is_err());
+        assert!(config.apply_yaml("timeout: 5\nmax-redirects: 0").is_ok());
+        assert_eq!(config.timeout_secs, 5);
+        assert_eq!(config.max_redirects, 0);
+    }
+
+    /// The response has the Url of the page after following redirects
+    #[test]
+    fn test_downloader_final_url() {
+        let _m1 = mock("GET", "/old").with_status(301).with_header("location", "/new/").create();
+        let _m2 = mock("GET", "/new/")
+            .with_status(200)
+            .with_header("content-type", "text/html; charset=ISO-8859-1")
+            .with_body("new")
+            .create();
+
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/old").unwrap();
+        let response = DefaultDownloader::new(&DownloaderConfig::default()).fetch_page(&Task::new(url)).unwrap();
+
+        assert_eq!(response.url.path(), "/new/");
+        assert_eq!(response.content_type, Some(String::from("text/html")));
+        assert_eq!(response.charset, Some(String::from("iso-8859-1")));
+        assert!(response.download_time >= response.response_time);
+    }
+
+    /// Redirects to another host are not followed from a host with headers or credentials, and
+    /// are returned instead
+    #[test]
+    fn test_downloader_private_redirect() {
+        let port = Url::parse(&mockito::server_url()).unwrap().port().unwrap();
+        let location = format!("http://localhost:{}/elsewhere", port);
+        let _m1 = mock("GET", "/private").with_status(302).with_header("location", &location).create();
+        let _m2 = mock("GET", "/elsewhere").with_status(200).with_body("elsewhere").create();
+
+        let mut config = DownloaderConfig::default();
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/private").unwrap();
+        assert_eq!(DefaultDownloader::new(&config).fetch_page(&Task::new(url.clone())).unwrap().body, b"elsewhere".to_vec());
+
+        config.apply_yaml("hosts:\n  127.0.0.1:\n    X-Token: secret").unwrap();
+        match DefaultDownloader::new(&config).fetch_page(&Task::new(url)).unwrap_err().kind {
+            Redirect(302, Some(ref redirect)) if *redirect == location => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_content_type() {
+        assert_eq!(parse_content_type("text/html"), (Some(String::from("text/html")), None));
+        assert_eq!(parse_content_type("Text/HTML; Charset=\"UTF-8\""), (Some(String::from("text/html")), Some(String::from("utf-8"))));
+        assert_eq!(parse_content_type("application/xml; q=1; charset=latin1"), (Some(String::from("application/xml")), Some(String::from("latin1"))));
+        assert_eq!(parse_content_type(""), (None, None));
+    }
+
+    /// Pages of media types that are not allowed are rejected, and Urls that look binary are
+    /// checked by their extension, or with a HEAD request if it is unknown, before they are
+    /// downloaded
+    #[test]
+    fn test_downloader_media_types() {
+        let _m1 = mock("GET", "/archive").with_status(200).with_header("content-type", "application/zip").create();
+        let m2 = mock("HEAD", "/video.mp4").with_status(200).with_header("content-type", "video/mp4").expect(0).create();
+        let m3 = mock("GET", "/video.mp4").with_status(200).with_header("content-type", "video/mp4").expect(0).create();
+        let _m4 = mock("GET", "/style.css").with_status(200).with_header("content-type", "text/css").create();
+        let m5 = mock("HEAD", "/model.stl").with_status(200).with_header("content-type", "model/stl").create();
+        let m6 = mock("GET", "/model.stl").with_status(200).with_header("content-type", "model/stl").expect(0).create();
+
+        let downloader = DefaultDownloader::new(&DownloaderConfig::default());
+        let fetch = |path: &str| {
+            let url = Url::parse(&mockito::server_url()).unwrap().join(path).unwrap();
+            downloader.fetch_page(&Task::new(url))
+        };
+
+        match fetch("/archive").unwrap_err().kind {
+            RejectedMediaType(ref media_type) if media_type == "application/zip" => {}
+            _ => panic!(),
+        }
+        match fetch("/video.mp4").unwrap_err().kind {
+            RejectedMediaType(ref media_type) if media_type == "video/mp4" => {}
+            _ => panic!(),
+        }
+        m2.assert();
+        m3.assert();
+
+        let config = DownloaderConfig { head_extensions: vec![String::from("stl")], ..DownloaderConfig::default() };
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/model.stl").unwrap();
+        match DefaultDownloader::new(&config).fetch_page(&Task::new(url)).unwrap_err().kind {
+            RejectedMediaType(ref media_type) if media_type == "model/stl" => {}
+            _ => panic!(),
+        }
+        m5.assert();
+        m6.assert();
+
+        assert!(fetch("/style.css").is_err());
+        let config = DownloaderConfig { allowed_media_types: vec![String::from("text/*")], ..DownloaderConfig::default() };
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/style.css").unwrap();
+        assert!(DefaultDownloader::new(&config).fetch_page(&Task::new(url)).is_ok());
+    }
+
+    /// Proxies that cannot be reached are taken out of rotation
+    #[test]
+    fn test_downloader_unreachable_proxy() {
+        let mut config = DownloaderConfig::default();
+        config.apply_yaml("
+proxy:
+  urls: [http://127.0.0.1:1]
+  max-failures: 2
+").unwrap();
+        let downloader = DefaultDownloader::new(&config);
+        let url = Url::parse(&mockito::server_url()).unwrap().join("/proxied").unwrap();
+
+        for _ in 0..3 {
+            assert!(downloader.fetch_page(&Task::new(url.clone())).is_err());
+        }
+        let health = downloader.proxy_pool().unwrap().health();
+        assert_eq!(health[0].0, "http://127.0.0.1:1/");
+        assert_eq!((health[0].1.requests, health[0].1.available), (2, false));
+    }
+
+    /// Basic and bearer credentials are only sent to their exact host over https, while login
+    /// forms apply to subdomains as well
+    #[test]
+    fn test_request_credentials() {
+        let credentials = Secrets::from_yaml("
+hosts:
+  intranet.aau.dk:
+    basic: {username: crawler, password: hunter2}
+  portal.aau.dk:
+    form: {url: https://portal.aau.dk/login, fields: {}}
+").unwrap().hosts;
+        let find = |url: &str| request_credentials(&credentials, &Url::parse(url).unwrap());
+
+        assert!(find("https://intranet.aau.dk/").is_some());
+        assert!(find("http://intranet.aau.dk/").is_none());
+        assert!(find("https://www.intranet.aau.dk/").is_none());
+        assert!(find("https://intranet.aau.dk.evil.com/").is_none());
+        assert!(find("http://www.portal.aau.dk/").is_some());
+    }
+
+    /// Basic and bearer credentials are not sent over plain http
+    #[test]
+    fn test_downloader_credentials() {
+        let _m1 = mock("GET", "/basic")
+            .match_header("authorization", Matcher::Missing)
+            .with_status(200)
+            .create();
+        let _m2 = mock("GET", "/bearer")
+            .match_header("authorization", Matcher::Missing)
+            .with_status(200)
+            .create();
+
+        let basic = DownloaderConfig {
+            credentials: Secrets::from_yaml("hosts:\n  127.0.0.1:\n    basic: {username: crawler, password: hunter2}").unwrap().hosts,
+            ..DownloaderConfig::default()
+        };
+        let bearer = DownloaderConfig {
+            credentials: Secrets::from_yaml("hosts:\n  127.0.0.1:\n    bearer: token").unwrap().hosts,
+            ..DownloaderConfig::default()
+        };
+        let url = Url::parse(&mockito::server_url()).unwrap();
+
+        assert!(DefaultDownloader::new(&basic).fetch_page(&Task::new(url.join("/basic").unwrap())).is_ok());
+        assert!(DefaultDownloader::new(&bearer).fetch_page(&Task::new(url.join("/bearer").unwrap())).is_ok());
+    }
+
+    /// The login form is posted once before the first request, and its session cookie is sent
+    /// with the following requests
+    #[test]
+    fn test_downloader_form_login() {
+        let login = mock("POST", "/login")
+            .match_body("pass=hunter2&user=crawler")
+            .with_status(200)
+            .with_header("set-cookie", "session=abc; Path=/")
+            .expect(1)
+            .create();
+        let _m = mock("GET", "/members")
+            .match_header("cookie", "session=abc")
+            .with_status(200)
+            .expect(2)
+            .create();
+
+        let url = Url::parse(&mockito::server_url()).unwrap();
+        let config = DownloaderConfig {
+            credentials: Secrets::from_yaml(&format!(
+                "hosts:\n  127.0.0.1:\n    form:\n      url: {}\n      fields: {{user: crawler, pass: hunter2}}",
+                url.join("/login").unwrap()
+            )).unwrap().hosts,
+            ..DownloaderConfig::default()
+        };
+        let downloader = DefaultDownloader::new(&config);
+
+        for _ in 0..2 {
+            assert!(downloader.fetch_page(&Task::new(url.join("/members").unwrap())).is_ok());
+        }
+        login.assert();
+    }
+
+    /// The login form is posted again when the session has expired, and the request is repeated
+    #[test]
+    fn test_downloader_form_login_expired() {
+        let login = mock("POST", "/relogin")
+            .with_status(200)
+            .with_header("set-cookie", "renewed=abc; Path=/")
+            .expect(1)
+            .create();
+        let _m1 = mock("GET", "/account").with_status(401).create();
+        let _m2 = mock("GET", "/account")
+            .match_header("cookie", "renewed=abc")
+            .with_status(200)
+            .create();
+
+        let url = Url::parse(&mockito::server_url()).unwrap();
+        let config = DownloaderConfig {
+            credentials: Secrets::from_yaml(&format!(
+                "hosts:\n  127.0.0.1:\n    form:\n      url: {}\n      fields: {{user: crawler}}",
+                url.join("/relogin").unwrap()
+            )).unwrap().hosts,
+            ..DownloaderConfig::default()
+        };
+        let downloader = DefaultDownloader::new(&config);
+        // The session of an earlier login has expired
+        *downloader.login_state(None, "127.0.0.1").lock().unwrap() = true;
+
+        assert!(downloader.fetch_page(&Task::new(url.join("/account").unwrap())).is_ok());
+        login.assert();
+    }
+
+    /// Unsuccessful responses are errors described by their status code
+    #[test]
+    fn test_downloader_status() {
+        let _m1 = mock("GET", "/missing").with_status(404).with_body("missing").create();
+        let _m2 = mock("GET", "/busy").with_status(503).with_header("retry-after", "120").create();
+        let _m3 = mock("GET", "/limited").with_status(429).create();
+        let _m4 = mock("GET", "/moved").with_status(301).with_header("location", "/new").create();
+
+        let config = DownloaderConfig { max_redirects: 0, ..DownloaderConfig::default() };
+        let downloader = DefaultDownloader::new(&config);
+        let fetch = |path: &str| {
+            let url = Url::parse(&mockito::server_url()).unwrap().join(path).unwrap();
+            downloader.fetch_page(&Task::new(url)).unwrap_err().kind
+        };
+
+        match fetch("/missing") {
+            ClientError(404) => {}
+            _ => panic!(),
+        }
+        match fetch("/busy") {
+            ServerError(503, Some(120_000)) => {}
+            _ => panic!(),
+        }
+        match fetch("/limited") {
+            ServerError(429, None) => {}
+            _ => panic!(),
+        }
+        match fetch("/moved") {
+            Redirect(301, Some(ref location)) if location == "/new" => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT"), Some(1_445_412_480));
+        assert_eq!(parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT"), Some(1_709_164_800));
+        assert_eq!(parse_http_date("21 Oct 2015"), None);
+
+        assert_eq!(parse_retry_after("30", 0), Some(30_000));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", 1_445_412_470), Some(10_000));
+        // Dates in the past mean that the task may be retried at once
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", 1_445_412_490), Some(0));
+        assert_eq!(parse_retry_after("soon", 0), None);
     }
 }
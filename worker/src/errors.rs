@@ -1,10 +1,11 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::ops::Deref;
-use crate::traits::TaskProcessResult;
+use crate::traits::{TaskFailure, TaskProcessResult};
 
 #[derive(Debug)]
 pub enum ManagerErrorKind {
+    #[allow(dead_code)] // Not reported by any manager yet
     NetworkError,        // No internet
     UnreachableError,    // No response
     InvalidTask,         // Task is not correct
@@ -13,9 +14,18 @@ pub enum ManagerErrorKind {
 #[derive(Debug)]
 pub enum DownloadErrorKind {
     NetworkError,        // No internet
+    #[allow(dead_code)] // Not reported by any downloader yet
     UnreachableError,    // No response
+    #[allow(dead_code)] // Not reported by any downloader yet
     InvalidURL,          // Url is invalid
     InvalidPage,         // Could not make sense of downloaded material
+    HostBusy(u64),       // Host was visited too recently. Contains milliseconds until it may be visited
+    NotModified,         // Page was not modified since it was last downloaded
+    Redirect(u16, Option<String>),    // Page redirects elsewhere. Contains the status code and the location
+    ClientError(u16),                 // Request was refused. Contains the status code
+    ServerError(u16, Option<u64>),    // Host failed or is overloaded. Contains the status code and the Retry-After in milliseconds
+    RejectedMediaType(String),        // Page is not of an allowed media type. Contains the media type
+    NotCached,           // Page is not in the cache, and the cache may not download it
 }
 
 #[derive(Debug)]
@@ -30,6 +40,7 @@ pub enum NormaliseErrorKind {
 
 #[derive(Debug)]
 pub enum ArchiveErrorKind {
+    #[allow(dead_code)] // Not reported by any archive yet
     NetworkError,        // No internet
     UnreachableError,    // No response
     ServerError,         // Backend received data, but was unable to process it
@@ -88,10 +99,11 @@ impl ManagerError {
 // Allows our error to be converted to a appropriate TaskProcessResult
 impl From<ManagerError> for TaskProcessResult {
     fn from(e: ScraperError<ManagerErrorKind>) -> Self {
+        let failure = TaskFailure::from(&e);
         match e.kind {
-            ManagerErrorKind::NetworkError => TaskProcessResult::Reject,
-            ManagerErrorKind::UnreachableError => TaskProcessResult::Reject,
-            ManagerErrorKind::InvalidTask => TaskProcessResult::Err,
+            ManagerErrorKind::NetworkError => TaskProcessResult::Reject(failure),
+            ManagerErrorKind::UnreachableError => TaskProcessResult::Reject(failure),
+            ManagerErrorKind::InvalidTask => TaskProcessResult::Err(failure),
         }
     }
 }
@@ -110,11 +122,56 @@ impl DownloadError {
 // Allows our error to be converted to a appropriate TaskProcessResult
 impl From<DownloadError> for TaskProcessResult {
     fn from(e: ScraperError<DownloadErrorKind>) -> Self {
+        let failure = TaskFailure::from(&e);
         match e.kind {
-            DownloadErrorKind::NetworkError => TaskProcessResult::Reject,
-            DownloadErrorKind::UnreachableError => TaskProcessResult::Reject,
-            DownloadErrorKind::InvalidURL => TaskProcessResult::Err,
-            DownloadErrorKind::InvalidPage => TaskProcessResult::Err,
+            DownloadErrorKind::NetworkError => TaskProcessResult::Reject(failure),
+            DownloadErrorKind::UnreachableError => TaskProcessResult::Reject(failure),
+            DownloadErrorKind::InvalidURL => TaskProcessResult::Err(failure),
+            DownloadErrorKind::InvalidPage => TaskProcessResult::Err(failure),
+            DownloadErrorKind::HostBusy(wait_ms) => TaskProcessResult::Postpone(wait_ms),
+            // There is nothing new to resolve, so the task is completed
+            DownloadErrorKind::NotModified => TaskProcessResult::Ok,
+            // The redirect is followed as a new task
+            DownloadErrorKind::Redirect(_, Some(_)) => TaskProcessResult::Ok,
+            DownloadErrorKind::Redirect(_, None) => TaskProcessResult::Err(failure),
+            DownloadErrorKind::ClientError(_) => TaskProcessResult::Err(failure),
+            DownloadErrorKind::ServerError(_, retry_after_ms) => TaskProcessResult::Reject(TaskFailure {
+                retry_after_ms,
+                ..failure
+            }),
+            // The page is deliberately skipped, so there is nothing to retry
+            DownloadErrorKind::RejectedMediaType(_) => TaskProcessResult::Ok,
+            DownloadErrorKind::NotCached => TaskProcessResult::Err(failure),
+        }
+    }
+}
+
+impl DownloadErrorKind {
+    /// The name of the kind without its details
+    pub fn name(&self) -> &'static str {
+        match self {
+            DownloadErrorKind::NetworkError => "NetworkError",
+            DownloadErrorKind::UnreachableError => "UnreachableError",
+            DownloadErrorKind::InvalidURL => "InvalidURL",
+            DownloadErrorKind::InvalidPage => "InvalidPage",
+            DownloadErrorKind::HostBusy(_) => "HostBusy",
+            DownloadErrorKind::NotModified => "NotModified",
+            DownloadErrorKind::Redirect(_, _) => "Redirect",
+            DownloadErrorKind::ClientError(_) => "ClientError",
+            DownloadErrorKind::ServerError(_, _) => "ServerError",
+            DownloadErrorKind::RejectedMediaType(_) => "RejectedMediaType",
+            DownloadErrorKind::NotCached => "NotCached",
+        }
+    }
+
+    /// The HTTP status code of the response that caused the error, if there was a response
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            DownloadErrorKind::NotModified => Some(304),
+            DownloadErrorKind::Redirect(status, _) => Some(*status),
+            DownloadErrorKind::ClientError(status) => Some(*status),
+            DownloadErrorKind::ServerError(status, _) => Some(*status),
+            _ => None,
         }
     }
 }
@@ -133,8 +190,9 @@ impl ExtractError {
 // Allows our error to be converted to a appropriate TaskProcessResult
 impl From<ExtractError> for TaskProcessResult {
     fn from(e: ScraperError<ExtractErrorKind>) -> Self {
+        let failure = TaskFailure::from(&e);
         match e.kind {
-            ExtractErrorKind::ParsingError => TaskProcessResult::Err,
+            ExtractErrorKind::ParsingError => TaskProcessResult::Err(failure),
         }
     }
 }
@@ -153,8 +211,9 @@ impl NormaliseError {
 // Allows our error to be converted to a appropriate TaskProcessResult
 impl From<NormaliseError> for TaskProcessResult {
     fn from(e: ScraperError<NormaliseErrorKind>) -> Self {
+        let failure = TaskFailure::from(&e);
         match e.kind {
-            NormaliseErrorKind::ParsingError => TaskProcessResult::Err,
+            NormaliseErrorKind::ParsingError => TaskProcessResult::Err(failure),
         }
     }
 }
@@ -173,15 +232,29 @@ impl ArchiveError {
 // Allows our error to be converted to a appropriate TaskProcessResult
 impl From<ArchiveError> for TaskProcessResult {
     fn from(e: ScraperError<ArchiveErrorKind>) -> Self {
+        let failure = TaskFailure::from(&e);
         match e.kind {
-            ArchiveErrorKind::NetworkError => TaskProcessResult::Reject,
-            ArchiveErrorKind::UnreachableError => TaskProcessResult::Reject,
-            ArchiveErrorKind::ServerError => TaskProcessResult::Reject,
-            ArchiveErrorKind::InvalidData => TaskProcessResult::Err,
+            ArchiveErrorKind::NetworkError => TaskProcessResult::Reject(failure),
+            ArchiveErrorKind::UnreachableError => TaskProcessResult::Reject(failure),
+            ArchiveErrorKind::ServerError => TaskProcessResult::Reject(failure),
+            ArchiveErrorKind::InvalidData => TaskProcessResult::Err(failure),
         }
     }
 }
 
+// Allows our errors to be described as the failure of a task
+impl<K> From<&ScraperError<K>> for TaskFailure
+where
+    K: Display + Debug
+{
+    fn from(e: &ScraperError<K>) -> Self {
+        TaskFailure {
+            kind: format!("{:?}", e.kind),
+            msg: e.to_string(),
+            retry_after_ms: None,
+        }
+    }
+}
 
 // Allows our errors to be displayed
 impl<K> Display for ScraperError<K>
@@ -237,6 +310,7 @@ impl Display for ArchiveErrorKind {
 #[cfg(test)]
 mod tests {
     use crate::errors::{ArchiveError, ArchiveErrorKind, DownloadError, DownloadErrorKind, ExtractError, ExtractErrorKind, ManagerError, ManagerErrorKind, NormaliseError, NormaliseErrorKind};
+    use crate::traits::TaskProcessResult;
 
     /// Testing formatting of ManagerError without source error
     #[test]
@@ -289,4 +363,52 @@ mod tests {
         let expected_str = "ServerError: Server tried to download something and failed (source: ParsingError: Trying to test nested errors)";
         assert_eq!(format!("{}", error), expected_str);
     }
-}
\ No newline at end of file
+
+    /// Testing that a permanent error becomes a failed task with the kind and message of the error
+    #[test]
+    fn download_error_to_task_process_result() {
+        let error = DownloadError::new(
+            DownloadErrorKind::InvalidPage,
+            "Could not read downloaded page",
+            None,
+        );
+        match TaskProcessResult::from(error) {
+            TaskProcessResult::Err(failure) => {
+                assert_eq!(failure.kind, "InvalidPage");
+                assert_eq!(failure.msg, "InvalidPage: Could not read downloaded page");
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Testing that a temporary error becomes a rejected task
+    #[test]
+    fn manager_error_to_task_process_result() {
+        let error = ManagerError::new(
+            ManagerErrorKind::UnreachableError,
+            "Could not reach manager.",
+            None,
+        );
+        match TaskProcessResult::from(error) {
+            TaskProcessResult::Reject(failure) => assert_eq!(failure.kind, "UnreachableError"),
+            _ => panic!(),
+        }
+    }
+
+    /// Testing that 4xx responses fail permanently and 5xx responses are retried after their
+    /// Retry-After
+    #[test]
+    fn status_error_to_task_process_result() {
+        let error = DownloadError::new(DownloadErrorKind::ClientError(404), "Page was not found", None);
+        match TaskProcessResult::from(error) {
+            TaskProcessResult::Err(failure) => assert_eq!(failure.kind, "ClientError(404)"),
+            _ => panic!(),
+        }
+
+        let error = DownloadError::new(DownloadErrorKind::ServerError(503, Some(120_000)), "Host is unavailable", None);
+        match TaskProcessResult::from(error) {
+            TaskProcessResult::Reject(failure) => assert_eq!(failure.retry_after_ms, Some(120_000)),
+            _ => panic!(),
+        }
+    }
+}
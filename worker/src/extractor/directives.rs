@@ -0,0 +1,113 @@
+use reqwest::header::HeaderMap;
+
+/// Directives of the X-Robots-Tag header that take a value, and so contain a colon without
+/// being scoped to a user agent
+const VALUED_DIRECTIVES: [&str; 4] = ["unavailable_after", "max-snippet", "max-image-preview", "max-video-preview"];
+
+/// How links that a page asks not to be followed are treated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NofollowMode {
+    /// Nofollow links are dropped
+    Suppress,
+    /// Nofollow links are followed with the lowest priority
+    Deprioritise,
+    /// Nofollow links are followed like any other link
+    Ignore,
+}
+
+/// The DirectivePolicy decides which robots directives of a page are obeyed
+#[derive(Debug, Clone, Copy)]
+pub struct DirectivePolicy {
+    pub nofollow: NofollowMode,
+    /// Whether the data of pages marked noindex is left out of the archive
+    pub noindex: bool,
+}
+
+impl Default for DirectivePolicy {
+    fn default() -> Self {
+        DirectivePolicy {
+            nofollow: NofollowMode::Suppress,
+            noindex: true,
+        }
+    }
+}
+
+/// The robots directives that apply to a page as a whole, given by <meta name="robots"> tags
+/// and X-Robots-Tag headers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RobotsDirectives {
+    /// The page should not be archived
+    pub noindex: bool,
+    /// The links of the page should not be followed
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// Add the directives of a comma-separated list, e.g. the content of a <meta name="robots">
+    pub fn add(&mut self, directives: &str) {
+        for directive in directives.split(',') {
+            match directive.trim().to_ascii_lowercase().as_str() {
+                "noindex" => self.noindex = true,
+                "nofollow" => self.nofollow = true,
+                "none" => {
+                    self.noindex = true;
+                    self.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Add the directives of the X-Robots-Tag headers. Headers scoped to a specific user agent,
+    /// e.g. "googlebot: noindex", are meant for other crawlers and are skipped.
+    pub fn add_headers(&mut self, headers: &HeaderMap) {
+        for value in headers.get_all("x-robots-tag").iter().filter_map(|value| value.to_str().ok()) {
+            let first = value.split(',').next().unwrap_or("");
+            let scoped = match first.find(':') {
+                Some(colon) => !VALUED_DIRECTIVES.contains(&first[..colon].trim().to_ascii_lowercase().as_str()),
+                None => false,
+            };
+            if !scoped {
+                self.add(value);
+            }
+        }
+    }
+}
+
+/// Whether the rel attribute of a link asks for it not to be followed
+pub fn is_nofollow(rel: &str) -> bool {
+    rel.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("nofollow"))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderMap;
+
+    use crate::extractor::directives::{is_nofollow, RobotsDirectives};
+
+    #[test]
+    fn parse_directives() {
+        let mut directives = RobotsDirectives::default();
+        directives.add("index, NoFollow");
+        assert_eq!(directives, RobotsDirectives { noindex: false, nofollow: true });
+
+        let mut directives = RobotsDirectives::default();
+        directives.add("none");
+        assert_eq!(directives, RobotsDirectives { noindex: true, nofollow: true });
+
+        assert!(is_nofollow("external nofollow"));
+        assert!(!is_nofollow("next"));
+    }
+
+    /// Headers scoped to another user agent are skipped
+    #[test]
+    fn parse_header_directives() {
+        let mut headers = HeaderMap::new();
+        headers.append("x-robots-tag", "googlebot: nofollow".parse().unwrap());
+        headers.append("x-robots-tag", "unavailable_after: 25 Jun 2030 15:00:00 PST, noindex".parse().unwrap());
+
+        let mut directives = RobotsDirectives::default();
+        directives.add_headers(&headers);
+        assert_eq!(directives, RobotsDirectives { noindex: true, nofollow: false });
+    }
+}
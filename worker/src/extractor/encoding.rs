@@ -0,0 +1,130 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+use crate::traits::Response;
+
+/// The number of bytes at the start of a page that are searched for a <meta> charset, as in
+/// the prescan of the HTML standard
+const META_PRESCAN_BYTES: usize = 1024;
+
+/// Decode the page of the response to UTF-8. The charset of the decoded response is the
+/// encoding that the page was decoded from. Bytes that are invalid in the encoding are replaced
+/// rather than failing the page.
+pub(crate) fn decode(response: Response<Vec<u8>>) -> Response<String> {
+    let (encoding, bom_length) = detect_encoding(&response);
+    let content = encoding
+        .decode_without_bom_handling(&response.body[bom_length..])
+        .0
+        .into_owned();
+
+    let mut response = response.with_body(content);
+    response.charset = Some(encoding.name().to_lowercase());
+    response
+}
+
+/// Detect the encoding of the page of the response along with the length of its byte order
+/// mark. In order of precedence, the encoding is given by the byte order mark, the charset of
+/// the Content-Type header, a <meta> charset, or else guessed from the bytes of the page.
+pub(crate) fn detect_encoding(response: &Response<Vec<u8>>) -> (&'static Encoding, usize) {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(&response.body) {
+        return (encoding, bom_length);
+    }
+
+    let declared = response.charset.as_ref()
+        .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+        .or_else(|| meta_charset(&response.body));
+    if let Some(encoding) = declared {
+        return (encoding, 0);
+    }
+
+    // The top level domain hints at the legacy encodings used in the region of the host
+    let tld = response.url.host_str()
+        .and_then(|host| host.rsplit('.').next())
+        .map(str::as_bytes);
+    let mut detector = EncodingDetector::new();
+    detector.feed(&response.body, true);
+    (detector.guess(tld, true), 0)
+}
+
+/// Find the charset declared by a <meta charset> or <meta http-equiv="Content-Type"> tag at the
+/// start of the page
+fn meta_charset(page: &[u8]) -> Option<&'static Encoding> {
+    let start = &page[..page.len().min(META_PRESCAN_BYTES)];
+    let start = String::from_utf8_lossy(start).to_ascii_lowercase();
+
+    start.split("<meta").skip(1)
+        .filter_map(|tag| {
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            let value = &tag[tag.find("charset=")? + "charset=".len()..];
+            let value = value.trim_start_matches(&['"', '\'', ' '][..]);
+            let end = value.find(|c: char| c == '"' || c == '\'' || c == ';' || c == '/' || c.is_whitespace())
+                .unwrap_or(value.len());
+            Encoding::for_label(&value.as_bytes()[..end])
+        })
+        .next()
+        // A page cannot declare itself UTF-16 in a way that can be read, so it is UTF-8
+        .map(|encoding| if encoding == UTF_16LE || encoding == UTF_16BE { UTF_8 } else { encoding })
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{SHIFT_JIS, UTF_8, WINDOWS_1252};
+    use url::Url;
+
+    use crate::extractor::encoding::{decode, detect_encoding};
+    use crate::traits::Response;
+
+    fn response(url: &str, charset: Option<&str>, body: &[u8]) -> Response<Vec<u8>> {
+        let mut response = Response::new(Url::parse(url).unwrap(), body.to_vec());
+        response.charset = charset.map(String::from);
+        response
+    }
+
+    /// "Æbler på Ålborg" in Windows-1252
+    const DANISH: &[u8] = b"<p>\xc6bler p\xe5 \xc5lborg</p>";
+
+    #[test]
+    fn decode_content_type_charset() {
+        let decoded = decode(response("http://aau.dk/", Some("iso-8859-1"), DANISH));
+        assert_eq!(decoded.body, "<p>Æbler på Ålborg</p>");
+        // ISO-8859-1 is decoded as its superset Windows-1252
+        assert_eq!(decoded.charset, Some(String::from("windows-1252")));
+    }
+
+    /// The byte order mark takes precedence over the header, and is removed
+    #[test]
+    fn decode_byte_order_mark() {
+        let decoded = decode(response("http://aau.dk/", Some("iso-8859-1"), "\u{feff}Å".as_bytes()));
+        assert_eq!(decoded.body, "Å");
+        assert_eq!(decoded.charset, Some(String::from("utf-8")));
+    }
+
+    #[test]
+    fn detect_meta_charset() {
+        let page = b"<html><head><meta charset=\"Shift_JIS\"></head></html>";
+        assert_eq!(detect_encoding(&response("http://example.jp/", None, page)), (SHIFT_JIS, 0));
+
+        let page = b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1252\">";
+        assert_eq!(detect_encoding(&response("http://aau.dk/", None, page)), (WINDOWS_1252, 0));
+
+        // UTF-16 cannot be declared by content that is readable as ASCII
+        let page = b"<meta charset=utf-16>";
+        assert_eq!(detect_encoding(&response("http://aau.dk/", None, page)), (UTF_8, 0));
+    }
+
+    /// Pages that declare no encoding are guessed from their bytes
+    #[test]
+    fn detect_undeclared_encoding() {
+        assert_eq!(detect_encoding(&response("http://aau.dk/", None, DANISH)).0, WINDOWS_1252);
+        assert_eq!(detect_encoding(&response("http://aau.dk/", None, "<p>Æbler</p>".as_bytes())).0, UTF_8);
+    }
+
+    /// An undeclared Shift_JIS page is decoded as Shift_JIS
+    #[test]
+    fn decode_undeclared_shift_jis() {
+        let (page, _, _) = SHIFT_JIS.encode("<p>日本語のウェブページです。東京大学の研究ニュース</p>");
+        let decoded = decode(response("http://example.com/", None, &page));
+        assert_eq!(decoded.body, "<p>日本語のウェブページです。東京大学の研究ニュース</p>");
+        assert_eq!(decoded.charset, Some(String::from("shift_jis")));
+    }
+}
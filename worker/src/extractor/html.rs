@@ -1,60 +1,162 @@
 use std::marker::PhantomData;
 
-use scraper::{Html, Selector};
+use reqwest::header::HeaderMap;
+use scraper::{ElementRef, Html, Selector};
 use url::Url;
 
-use crate::errors::{ExtractError, ExtractResult};
-use crate::errors::ExtractErrorKind::ParsingError;
-use crate::traits::Extractor;
+use crate::credentials::redact_url;
+use crate::downloader::parse_list;
+use crate::errors::ExtractResult;
+use crate::extractor::directives::{DirectivePolicy, is_nofollow, NofollowMode, RobotsDirectives};
+use crate::extractor::encoding::decode;
+use crate::traits::{Extractor, Link, LinkSource, Response};
 
-/// The HTMLExtractorBase is an Extractor that converts a page of bytes (u8) to HTML and
-/// uses a HTMLExtractor to extract target data.
+/// The HTMLExtractorBase is an Extractor that decodes a page of bytes (u8) from its detected
+/// encoding, converts it to HTML, and uses a HTMLExtractor to extract target data. Relative links
+/// are resolved against the Url of the response, i.e. the Url after following redirects.
+///
+/// The robots directives of the page, given by <meta name="robots"> tags and X-Robots-Tag
+/// headers, and the rel=nofollow of its links are obeyed according to the DirectivePolicy.
+/// The canonical Url of the page, if it declares one on the host of the page, is reported as a
+/// link of its own source.
 pub struct HTMLExtractorBase<D, H: HTMLExtractor<D>> {
     _marker: PhantomData<D>,
     html_extractor: H,
+    policy: DirectivePolicy,
+    robots_selector: Selector,
+    base_selector: Selector,
+    canonical_selector: Selector,
 }
 
 impl<D, H> Extractor<Vec<u8>, D> for HTMLExtractorBase<D, H>
 where
+    D: Sync,
     H: HTMLExtractor<D>,
 {
-    fn extract_content(&self, content: Vec<u8>, url: &Url) -> ExtractResult<(Vec<Url>, Vec<D>)> {
-        let html = String::from_utf8(content).map_err(|e| {
-            ExtractError::new(ParsingError, "Failed to parse html", Some(Box::new(e)))
-        })?;
-        let document = Html::parse_document(html.as_str());
+    fn extract_content(&self, response: Response<Vec<u8>>) -> ExtractResult<(Vec<Link>, Vec<D>)> {
+        let response = decode(response);
+        let document = Html::parse_document(response.body.as_str());
 
-        self.html_extractor.extract_from_html(document, url)
+        let mut directives = RobotsDirectives::default();
+        directives.add_headers(&response.headers);
+        for element in document.select(&self.robots_selector) {
+            if element.value().attr("name").unwrap_or("").eq_ignore_ascii_case("robots") {
+                directives.add(element.value().attr("content").unwrap_or(""));
+            }
+        }
+
+        // The Link header takes precedence over the page, as the page may be a template shared by several Urls
+        let canonical = header_canonical(&response.headers, &response.url).or_else(|| {
+            let base = base_url(&document, &self.base_selector, &response.url);
+            document.select(&self.canonical_selector)
+                .filter(|element| is_rel(element.value().attr("rel").unwrap_or(""), "canonical"))
+                .filter_map(|element| base.join(element.value().attr("href")?.trim()).ok())
+                .find(|url| url.scheme() == "http" || url.scheme() == "https")
+        })
+        // A page may not claim to be a copy of a page on another host
+        .filter(|url| url.host_str() == response.url.host_str());
+
+        let (mut links, mut data) = self.html_extractor.extract_from_html(document, &response)?;
+
+        if directives.noindex && self.policy.noindex {
+            debug!("Page {} is marked noindex, so its data is not archived", redact_url(&response.url));
+            data.clear();
+        }
+        if directives.nofollow {
+            for link in links.iter_mut() {
+                link.nofollow = true;
+            }
+        }
+        match self.policy.nofollow {
+            NofollowMode::Suppress => links.retain(|link| !link.nofollow),
+            NofollowMode::Deprioritise => {
+                for link in links.iter_mut().filter(|link| link.nofollow) {
+                    link.priority = Some(0);
+                }
+            }
+            NofollowMode::Ignore => {}
+        }
+
+        if let Some(url) = canonical {
+            let mut link = Link::new(url);
+            link.source = Some(LinkSource::Canonical);
+            links.push(link);
+        }
+
+        Ok((links, data))
     }
 }
 
 impl<D, H: HTMLExtractor<D>> HTMLExtractorBase<D, H> {
     /// Construct a new HTMLExtractorBase that extracts Urls and target data with the given
     /// HTMLExtractor
+    #[cfg(test)]
     pub fn new(html_extractor: H) -> HTMLExtractorBase<D, H> {
+        HTMLExtractorBase::with_policy(html_extractor, DirectivePolicy::default())
+    }
+
+    /// Construct a new HTMLExtractorBase that obeys the robots directives of pages according
+    /// to the given policy
+    pub fn with_policy(html_extractor: H, policy: DirectivePolicy) -> HTMLExtractorBase<D, H> {
         HTMLExtractorBase {
             _marker: PhantomData,
             html_extractor,
+            policy,
+            robots_selector: Selector::parse("meta[name][content]").expect("meta tag selector"),
+            base_selector: Selector::parse("base[href]").expect("base tag selector"),
+            canonical_selector: Selector::parse("link[rel][href]").expect("link tag selector"),
         }
     }
 }
 
-/// An HTMLExtractor extracts Urls and data from HTML.
-pub trait HTMLExtractor<D> {
-    fn extract_from_html(&self, content: Html, url: &Url) -> ExtractResult<(Vec<Url>, Vec<D>)>;
+/// An HTMLExtractor extracts links and data from HTML. The response holds the decoded page
+/// along with its Url and the encoding it was decoded from.
+pub trait HTMLExtractor<D>: Sync {
+    fn extract_from_html(&self, content: Html, response: &Response<String>) -> ExtractResult<(Vec<Link>, Vec<D>)>;
 }
 
+/// The link sources of a HTMLLinkExtractor by default, which are the elements that link to
+/// other pages rather than to resources of the page itself
+pub const DEFAULT_LINK_SOURCES: &str = "a,area,link,iframe,frame,meta-refresh";
+
 /// The HTMLLinkExtractor is a HTMLExtractor that only extracts links and no data. It finds
-/// Urls by taking the href attributes of the anchor tags. 
+/// Urls in the elements of the given link sources, e.g. the href attributes of the anchor tags,
+/// and keeps the text of the anchors. Relative Urls are resolved against the <base href> of the
+/// page, if it has one, and otherwise against the Url of the response.
 pub struct HTMLLinkExtractor {
     link_selector: Selector,
+    base_selector: Selector,
 }
 
 impl HTMLLinkExtractor {
-    /// Construct a new HTMLLinkExtractor
+    /// Construct a new HTMLLinkExtractor that finds links in anchor tags only
+    #[cfg(test)]
     pub fn new() -> HTMLLinkExtractor {
+        HTMLLinkExtractor::with_sources(&[LinkSource::Anchor])
+    }
+
+    /// Construct a new HTMLLinkExtractor that finds links in the elements of the given sources
+    pub fn with_sources(sources: &[LinkSource]) -> HTMLLinkExtractor {
+        let selectors: Vec<&str> = sources.iter()
+            .filter_map(|source| match source {
+                LinkSource::Anchor => Some("a[href]"),
+                LinkSource::Area => Some("area[href]"),
+                LinkSource::Link => Some("link[href][rel]"),
+                LinkSource::Iframe => Some("iframe[src]"),
+                LinkSource::Frame => Some("frame[src]"),
+                LinkSource::MetaRefresh => Some("meta[http-equiv][content]"),
+                LinkSource::Srcset => Some("img[srcset], source[srcset]"),
+                LinkSource::Form => Some("form[action]"),
+                // The canonical link is reported by the HTMLExtractorBase
+                LinkSource::Canonical => None,
+            })
+            .collect();
+        // An empty selector is invalid, so no sources select no elements instead
+        let selector = if selectors.is_empty() { String::from(":not(*)") } else { selectors.join(", ") };
+
         HTMLLinkExtractor {
-            link_selector: Selector::parse("a").expect("anchor tag selector"),
+            link_selector: Selector::parse(&selector).expect("link source selector"),
+            base_selector: Selector::parse("base[href]").expect("base tag selector"),
         }
     }
 }
@@ -63,33 +165,144 @@ impl HTMLExtractor<()> for HTMLLinkExtractor {
     fn extract_from_html(
         &self,
         content: Html,
-        reference_url: &Url,
-    ) -> ExtractResult<(Vec<Url>, Vec<()>)> {
+        response: &Response<String>,
+    ) -> ExtractResult<(Vec<Link>, Vec<()>)> {
         // Extract no data
-        // Urls are found in the href attributes of anchor tags
+        let base = base_url(&content, &self.base_selector, &response.url);
+
+        // Urls are found in the elements of the link sources
         // and check if they are either https or http
-        let tasks: Vec<Url> = content
+        let links: Vec<Link> = content
             .select(&self.link_selector)
-            .filter_map(|element| element.value().attr("href"))
-            .filter_map(|url| {
-                Url::options()
-                    .base_url(Some(&reference_url))
-                    .parse(url)
-                    .ok()
-            })
-            .filter_map(|url| {
-                if "https" == url.scheme() || url.scheme() == "http" {
-                    Some(url)
-                } else {
-                    None
-                }
+            .flat_map(|element| {
+                let (source, urls) = element_urls(element);
+                let text = match source {
+                    // Collapse the whitespace of the anchor text
+                    Some(LinkSource::Anchor) => element.text().collect::<Vec<_>>().join(" "),
+                    Some(LinkSource::Area) => String::from(element.value().attr("alt").unwrap_or("")),
+                    _ => String::new(),
+                };
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                let nofollow = is_nofollow(element.value().attr("rel").unwrap_or(""));
+                let base = &base;
+
+                urls.into_iter().filter_map(move |url| {
+                    let url = Url::options()
+                        .base_url(Some(base))
+                        .parse(url.trim())
+                        .ok()?;
+                    if "https" != url.scheme() && url.scheme() != "http" {
+                        return None;
+                    }
+
+                    let mut link = Link::new(url);
+                    link.source = source;
+                    link.nofollow = nofollow;
+                    if !text.is_empty() {
+                        link.text = Some(text.clone());
+                    }
+                    Some(link)
+                })
             })
             .collect();
 
-        Ok((tasks, vec![]))
+        Ok((links, vec![]))
+    }
+}
+
+/// The source of the selected element along with the unresolved Urls it links to. Elements
+/// that match the selector of a source but do not link, e.g. a <link rel=stylesheet>, have no Urls.
+fn element_urls<'a>(element: ElementRef<'a>) -> (Option<LinkSource>, Vec<&'a str>) {
+    let value = element.value();
+    match value.name() {
+        "a" => (Some(LinkSource::Anchor), value.attr("href").into_iter().collect()),
+        "area" => (Some(LinkSource::Area), value.attr("href").into_iter().collect()),
+        "link" => {
+            let rel = value.attr("rel").unwrap_or("");
+            let links = is_rel(rel, "next") || is_rel(rel, "prev") || is_rel(rel, "alternate");
+            (Some(LinkSource::Link), value.attr("href").filter(|_| links).into_iter().collect())
+        }
+        "iframe" => (Some(LinkSource::Iframe), value.attr("src").into_iter().collect()),
+        "frame" => (Some(LinkSource::Frame), value.attr("src").into_iter().collect()),
+        "meta" => {
+            let url = value.attr("content")
+                .filter(|_| value.attr("http-equiv").unwrap_or("").eq_ignore_ascii_case("refresh"))
+                .and_then(refresh_url);
+            (Some(LinkSource::MetaRefresh), url.into_iter().collect())
+        }
+        "img" | "source" => {
+            // Each candidate of a srcset is a Url optionally followed by a descriptor, e.g. "big.jpg 2x"
+            let urls = value.attr("srcset").unwrap_or("")
+                .split(',')
+                .filter_map(|candidate| candidate.split_whitespace().next())
+                .collect();
+            (Some(LinkSource::Srcset), urls)
+        }
+        "form" => {
+            // Forms are submitted with GET by default
+            let get = value.attr("method").unwrap_or("get").eq_ignore_ascii_case("get");
+            (Some(LinkSource::Form), value.attr("action").filter(|_| get).into_iter().collect())
+        }
+        _ => (None, vec![]),
     }
 }
 
+/// The Url that relative Urls of the page are resolved against, which is the first <base href>,
+/// itself relative to the page, or else the Url of the page
+fn base_url(document: &Html, base_selector: &Selector, url: &Url) -> Url {
+    document.select(base_selector).next()
+        .and_then(|element| url.join(element.value().attr("href")?).ok())
+        .unwrap_or_else(|| url.clone())
+}
+
+/// Whether the rel attribute contains the given link type
+fn is_rel(rel: &str, link_type: &str) -> bool {
+    rel.split_whitespace().any(|rel| rel.eq_ignore_ascii_case(link_type))
+}
+
+/// The canonical Url given by the Link headers of a response, e.g.
+/// `Link: <https://aau.dk/news>; rel="canonical"`
+fn header_canonical(headers: &HeaderMap, url: &Url) -> Option<Url> {
+    headers.get_all("link").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split('<').skip(1))
+        .filter_map(|link| {
+            let end = link.find('>')?;
+            let canonical = link[end + 1..].split(';').any(|param| {
+                let mut param = param.splitn(2, '=');
+                let name = param.next().unwrap_or("").trim();
+                let value = param.next().unwrap_or("").trim().trim_end_matches(',').trim().trim_matches('"');
+                name.eq_ignore_ascii_case("rel") && is_rel(value, "canonical")
+            });
+            if canonical { url.join(link[..end].trim()).ok() } else { None }
+        })
+        .find(|url| url.scheme() == "http" || url.scheme() == "https")
+}
+
+/// The Url of the content of a <meta http-equiv=refresh>, e.g. "5; url='/next'"
+fn refresh_url(content: &str) -> Option<&str> {
+    let url = content[content.find(&[';', ','][..])? + 1..].trim_start();
+    let url = if url.get(..3).filter(|prefix| prefix.eq_ignore_ascii_case("url")).is_some() {
+        url[3..].trim_start().trim_start_matches('=').trim_start()
+    } else {
+        url
+    };
+    let url = url.trim_matches(&['"', '\''][..]).trim();
+    if url.is_empty() {
+        None
+    } else {
+        Some(url)
+    }
+}
+
+/// Parse a comma-separated list of link source names. Unknown names are a mistake in the
+/// configuration, so they cause a panic.
+pub fn parse_link_sources(list: &str) -> Vec<LinkSource> {
+    parse_list(list).iter()
+        .map(|name| LinkSource::from_name(name).unwrap_or_else(|| panic!("Unknown link source {:?}", name)))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,12 +321,12 @@ mod tests {
             </html>";
         let url = Url::parse("http://ref.ref").unwrap();
 
-        let result = extractor.extract_content(test_string.as_bytes().to_vec(), &url);
+        let result = extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec()));
 
         match result {
             Ok((urls, _)) => {
                 assert_eq!(urls.len(), 1);
-                assert_eq!(urls[0].as_str(), "http://example.com/");
+                assert_eq!(urls[0].url.as_str(), "http://example.com/");
             }
             Err(_) => panic!(),
         }
@@ -132,12 +345,12 @@ mod tests {
             </html>";
         let url = Url::parse("http://ref.ref").unwrap();
 
-        let result = extractor.extract_content(test_string.as_bytes().to_vec(), &url);
+        let result = extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec()));
 
         match result {
             Ok((url, _)) => {
                 assert_eq!(url.len(), 1);
-                assert_eq!(url[0].as_str(), "http://ref.ref/test");
+                assert_eq!(url[0].url.as_str(), "http://ref.ref/test");
             }
             Err(_) => panic!(),
         }
@@ -159,14 +372,214 @@ mod tests {
             </html>";
         let url = Url::parse("http://ref.ref").unwrap();
 
-        let result = extractor.extract_content(test_string.as_bytes().to_vec(), &url);
+        let result = extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec()));
 
         match result {
             Ok((urls, _)) => {
                 assert_eq!(urls.len(), 1);
-                assert_eq!(urls[0].as_str(), "http://example.com/");
+                assert_eq!(urls[0].url.as_str(), "http://example.com/");
             }
             Err(_) => panic!(),
         }
     }
+
+    #[test]
+    fn test_link_extractor_text() {
+        let html_extractor = HTMLLinkExtractor::new();
+        let extractor = HTMLExtractorBase::new(html_extractor);
+
+        let test_string = "<!DOCTYPE html>
+            <html>
+            <body>
+            <a href=\"/news\">  Latest
+                <b>news</b> </a>
+            <a href=\"/empty\"> </a>
+            </body>
+            </html>";
+        let url = Url::parse("http://ref.ref").unwrap();
+
+        let result = extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec()));
+
+        match result {
+            Ok((urls, _)) => {
+                assert_eq!(urls.len(), 2);
+                assert_eq!(urls[0].text, Some(String::from("Latest news")));
+                assert_eq!(urls[1].text, None);
+            }
+            Err(_) => panic!(),
+        }
+    }
+
+    /// Pages that are not UTF-8 are decoded rather than failing
+    #[test]
+    fn test_link_extractor_latin1() {
+        let extractor = HTMLExtractorBase::new(HTMLLinkExtractor::new());
+
+        let page = b"<html><head><meta charset=\"iso-8859-1\"></head>
+            <body><a href=\"/nyheder\">Nyheder p\xe5 dansk</a></body></html>";
+        let url = Url::parse("http://ref.ref").unwrap();
+
+        let (urls, _) = extractor.extract_content(Response::new(url, page.to_vec())).unwrap();
+        assert_eq!(urls[0].text, Some(String::from("Nyheder på dansk")));
+    }
+
+    /// Links are found in the elements of every source, in the order of the page
+    #[test]
+    fn test_link_extractor_sources() {
+        let extractor = HTMLExtractorBase::new(HTMLLinkExtractor::with_sources(&LinkSource::ALL));
+
+        let test_string = "<html><head>
+            <meta http-equiv=\"Refresh\" content=\"5; URL='/moved'\">
+            <link rel=\"next\" href=\"/page/2\">
+            <link rel=\"alternate\" hreflang=\"en\" href=\"/en\">
+            <link rel=\"stylesheet\" href=\"/style.css\">
+            </head><body>
+            <map><area href=\"/room\" alt=\"Room\"></map>
+            <iframe src=\"/embedded\"></iframe>
+            <img srcset=\"/small.jpg 1x, /big.jpg 2x\">
+            <form action=\"/search\"></form>
+            <form method=\"post\" action=\"/login\"></form>
+            </body></html>";
+        let url = Url::parse("http://ref.ref").unwrap();
+
+        let (links, _) = extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec())).unwrap();
+        let found: Vec<_> = links.iter().map(|link| (link.url.path(), link.source.unwrap())).collect();
+        assert_eq!(found, vec![
+            ("/moved", LinkSource::MetaRefresh),
+            ("/page/2", LinkSource::Link),
+            ("/en", LinkSource::Link),
+            ("/room", LinkSource::Area),
+            ("/embedded", LinkSource::Iframe),
+            ("/small.jpg", LinkSource::Srcset),
+            ("/big.jpg", LinkSource::Srcset),
+            ("/search", LinkSource::Form),
+        ]);
+        assert_eq!(links[3].text, Some(String::from("Room")));
+    }
+
+    /// Only the configured sources are used
+    #[test]
+    fn test_link_extractor_configured_sources() {
+        let sources = parse_link_sources("a, iframe");
+        assert_eq!(sources, vec![LinkSource::Anchor, LinkSource::Iframe]);
+        let extractor = HTMLExtractorBase::new(HTMLLinkExtractor::with_sources(&sources));
+
+        let test_string = "<a href=\"/a\">a</a><frame src=\"/frame\"><iframe src=\"/iframe\"></iframe>";
+        let url = Url::parse("http://ref.ref").unwrap();
+
+        let (links, _) = extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec())).unwrap();
+        let found: Vec<_> = links.iter().map(|link| link.url.path()).collect();
+        assert_eq!(found, vec!["/a", "/iframe"]);
+    }
+
+    #[test]
+    fn test_link_extractor_base_href() {
+        let extractor = HTMLExtractorBase::new(HTMLLinkExtractor::new());
+
+        let test_string = "<html><head><base href=\"/docs/\"></head>
+            <body><a href=\"intro\">Intro</a><a href=\"/about\">About</a></body></html>";
+        let url = Url::parse("http://ref.ref/index.html").unwrap();
+
+        let (links, _) = extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec())).unwrap();
+        assert_eq!(links[0].url.as_str(), "http://ref.ref/docs/intro");
+        assert_eq!(links[1].url.as_str(), "http://ref.ref/about");
+    }
+
+    #[test]
+    fn test_refresh_url() {
+        assert_eq!(refresh_url("0; url=http://aau.dk/"), Some("http://aau.dk/"));
+        assert_eq!(refresh_url("3;URL=\"/next\""), Some("/next"));
+        assert_eq!(refresh_url("5, /other"), Some("/other"));
+        assert_eq!(refresh_url("30"), None);
+        assert_eq!(refresh_url("0; ééé"), Some("ééé"));
+        assert_eq!(refresh_url("0;é"), Some("é"));
+    }
+
+    /// An HTMLExtractor that extracts the page itself as data
+    struct PageExtractor;
+
+    impl HTMLExtractor<String> for PageExtractor {
+        fn extract_from_html(&self, _content: Html, response: &Response<String>) -> ExtractResult<(Vec<Link>, Vec<String>)> {
+            Ok((vec![], vec![response.body.clone()]))
+        }
+    }
+
+    #[test]
+    fn test_nofollow_modes() {
+        let test_string = "<a href=\"/followed\">a</a><a rel=\"nofollow\" href=\"/ad\">b</a>";
+        let extract = |nofollow| {
+            let policy = DirectivePolicy { nofollow, noindex: true };
+            let extractor = HTMLExtractorBase::with_policy(HTMLLinkExtractor::new(), policy);
+            let url = Url::parse("http://ref.ref").unwrap();
+            extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec())).unwrap().0
+        };
+
+        let links = extract(NofollowMode::Suppress);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].url.path(), "/followed");
+
+        let links = extract(NofollowMode::Deprioritise);
+        assert_eq!(links.iter().map(|link| link.priority).collect::<Vec<_>>(), vec![None, Some(0)]);
+
+        let links = extract(NofollowMode::Ignore);
+        assert_eq!(links.iter().map(|link| link.nofollow).collect::<Vec<_>>(), vec![false, true]);
+    }
+
+    /// Links of pages marked nofollow are nofollow links
+    #[test]
+    fn test_meta_robots_nofollow() {
+        let extractor = HTMLExtractorBase::new(HTMLLinkExtractor::new());
+
+        let test_string = "<html><head><meta name=\"ROBOTS\" content=\"noindex, nofollow\"></head>
+            <body><a href=\"/news\">News</a></body></html>";
+        let url = Url::parse("http://ref.ref").unwrap();
+
+        let (links, _) = extractor.extract_content(Response::new(url, test_string.as_bytes().to_vec())).unwrap();
+        assert!(links.is_empty());
+    }
+
+    /// The data of noindex pages is not archived, unless the policy allows it
+    #[test]
+    fn test_noindex() {
+        let mut response = Response::new(Url::parse("http://ref.ref").unwrap(), b"<p>page</p>".to_vec());
+        response.headers.insert("x-robots-tag", "noindex".parse().unwrap());
+
+        let extractor = HTMLExtractorBase::new(PageExtractor);
+        let (_, data) = extractor.extract_content(response.clone()).unwrap();
+        assert!(data.is_empty());
+
+        let policy = DirectivePolicy { nofollow: NofollowMode::Suppress, noindex: false };
+        let extractor = HTMLExtractorBase::with_policy(PageExtractor, policy);
+        let (_, data) = extractor.extract_content(response).unwrap();
+        assert_eq!(data.len(), 1);
+    }
+
+    #[test]
+    fn test_canonical() {
+        let extractor = HTMLExtractorBase::new(HTMLLinkExtractor::new());
+        let canonical = |response: Response<Vec<u8>>| {
+            let (links, _) = extractor.extract_content(response).unwrap();
+            links.into_iter()
+                .find(|link| link.source == Some(LinkSource::Canonical))
+                .map(|link| link.url.to_string())
+        };
+
+        let test_string = "<html><head><meta name=\"robots\" content=\"nofollow\">
+            <link rel=\"Canonical\" href=\"/news?id=1\"></head>
+            <body><a href=\"/other\">Other</a></body></html>";
+        let url = Url::parse("http://ref.ref/news?id=1&session=2").unwrap();
+        let mut response = Response::new(url, test_string.as_bytes().to_vec());
+        // The canonical link is reported even if the links of the page are not followed
+        assert_eq!(canonical(response.clone()), Some(String::from("http://ref.ref/news?id=1")));
+
+        response.headers.insert("link", "</style.css>; rel=preload, <http://ref.ref/article/1>; rel=\"canonical\"".parse().unwrap());
+        assert_eq!(canonical(response.clone()), Some(String::from("http://ref.ref/article/1")));
+
+        // Canonical urls on another host are ignored
+        response.headers.insert("link", "<http://other.ref/article/1>; rel=canonical".parse().unwrap());
+        assert_eq!(canonical(response), None);
+
+        let url = Url::parse("http://ref.ref").unwrap();
+        assert_eq!(canonical(Response::new(url, b"<a href=\"/a\">a</a>".to_vec())), None);
+    }
 }
@@ -1 +1,6 @@
+pub(crate) mod directives;
+pub(crate) mod encoding;
 pub(crate) mod html;
+pub(crate) mod rules;
+pub(crate) mod sitemap;
+pub(crate) mod structured;
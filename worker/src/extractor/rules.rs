@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ExtractResult;
+use crate::extractor::html::HTMLExtractor;
+use crate::traits::{Link, Response};
+
+/// Whether a field takes the first element matched by its selector or every element
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cardinality {
+    Single,
+    List,
+}
+
+/// A field of a rules file
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct FieldFile {
+    selector: String,
+    /// The attribute to take, or else the text of the element
+    attribute: Option<String>,
+    regex: Option<String>,
+    /// Single by default
+    cardinality: Option<Cardinality>,
+}
+
+/// A rule of a rules file
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RuleFile {
+    name: String,
+    url: String,
+    fields: BTreeMap<String, FieldFile>,
+}
+
+/// The content of a rules file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RulesFile {
+    rules: Vec<RuleFile>,
+}
+
+/// A Field describes how the value of a named field is found in a page
+struct Field {
+    name: String,
+    selector: Selector,
+    attribute: Option<String>,
+    /// Values are replaced by the first capture group of the regex, or the whole match if it has
+    /// no groups. Values that do not match are dropped.
+    regex: Option<Regex>,
+    cardinality: Cardinality,
+}
+
+/// A Rule extracts a record of named fields from the pages whose Url matches its pattern
+struct Rule {
+    name: String,
+    url: Regex,
+    fields: Vec<Field>,
+}
+
+/// The value of a field of a record
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Single(String),
+    List(Vec<String>),
+}
+
+/// A Record is the data extracted from a page by a rule
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Record {
+    pub url: String,
+    /// The encoding that the page was decoded from, e.g. "windows-1252"
+    pub charset: Option<String>,
+    /// The name of the rule, or the format of the structured data the record was harvested from
+    pub rule: String,
+    pub fields: BTreeMap<String, FieldValue>,
+}
+
+/// The Rules are the rules of a rules file, which describe what data to extract from which
+/// pages without writing an extractor for every site
+#[derive(Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Parse a YAML or JSON rules file
+    pub fn from_yaml(yaml: &str) -> Result<Self, Box<dyn Error>> {
+        let file: RulesFile = serde_yaml::from_str(yaml)?;
+        let mut rules = vec![];
+        for RuleFile { name: rule, url, fields: rule_fields } in file.rules {
+            let mut fields = vec![];
+            for (name, field) in rule_fields {
+                fields.push(Field {
+                    selector: Selector::parse(&field.selector)
+                        .map_err(|e| format!("Invalid selector of field {:?} of rule {:?}. {:?}", name, rule, e))?,
+                    attribute: field.attribute,
+                    regex: match field.regex {
+                        Some(regex) => Some(Regex::new(&regex)?),
+                        None => None,
+                    },
+                    cardinality: field.cardinality.unwrap_or(Cardinality::Single),
+                    name,
+                });
+            }
+            rules.push(Rule {
+                name: rule,
+                url: Regex::new(&url)?,
+                fields,
+            });
+        }
+        Ok(Rules { rules })
+    }
+
+    /// Read the YAML or JSON rules file at the given path
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let yaml = fs::read_to_string(path)?;
+        Rules::from_yaml(&yaml)
+    }
+}
+
+/// The RulesExtractor is a HTMLExtractor that extracts a Record from a page for every rule whose
+/// Url pattern matches the Url of the page, and has another HTMLExtractor extract the links of
+/// the page. Records with no fields found are left out.
+pub struct RulesExtractor<H: HTMLExtractor<()>> {
+    rules: Rules,
+    link_extractor: H,
+}
+
+impl<H: HTMLExtractor<()>> RulesExtractor<H> {
+    /// Construct a new RulesExtractor with the given rules, which extracts links with the given
+    /// HTMLExtractor
+    pub fn new(rules: Rules, link_extractor: H) -> Self {
+        RulesExtractor {
+            rules,
+            link_extractor,
+        }
+    }
+}
+
+impl<H: HTMLExtractor<()>> HTMLExtractor<Record> for RulesExtractor<H> {
+    fn extract_from_html(&self, content: Html, response: &Response<String>) -> ExtractResult<(Vec<Link>, Vec<Record>)> {
+        let records = self.rules.rules.iter()
+            .filter(|rule| rule.url.is_match(response.url.as_str()))
+            .filter_map(|rule| {
+                let fields: BTreeMap<String, FieldValue> = rule.fields.iter()
+                    .filter_map(|field| {
+                        let mut values = content.select(&field.selector)
+                            .filter_map(|element| field_value(field, element));
+                        let value = match field.cardinality {
+                            Cardinality::Single => FieldValue::Single(values.next()?),
+                            Cardinality::List => FieldValue::List(values.collect()),
+                        };
+                        Some((field.name.clone(), value))
+                    })
+                    .collect();
+
+                let found = fields.values().any(|value| match value {
+                    FieldValue::Single(_) => true,
+                    FieldValue::List(values) => !values.is_empty(),
+                });
+                if found {
+                    Some(Record {
+                        url: response.url.to_string(),
+                        charset: response.charset.clone(),
+                        rule: rule.name.clone(),
+                        fields,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let (links, _) = self.link_extractor.extract_from_html(content, response)?;
+        Ok((links, records))
+    }
+}
+
+/// The value of the field in the element, i.e. its attribute or its text with whitespace
+/// collapsed, after the regex of the field
+fn field_value(field: &Field, element: ElementRef) -> Option<String> {
+    let value = match &field.attribute {
+        Some(attribute) => element.value().attr(attribute)?.trim().to_string(),
+        None => element.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" "),
+    };
+
+    match &field.regex {
+        Some(regex) => {
+            let captures = regex.captures(&value)?;
+            let value = captures.get(1).or_else(|| captures.get(0))?;
+            Some(value.as_str().to_string())
+        }
+        None => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use url::Url;
+
+    use crate::extractor::html::{HTMLExtractorBase, HTMLLinkExtractor};
+    use crate::extractor::rules::{FieldValue, Record, Rules, RulesExtractor};
+    use crate::traits::{Extractor, Response};
+
+    const RULES: &str = r#"
+rules:
+  - name: news
+    url: "^https?://(www\\.)?aau\\.dk/news/"
+    fields:
+      title:
+        selector: h1
+      published:
+        selector: time
+        attribute: datetime
+      tags:
+        selector: ".tags a"
+        cardinality: list
+      id:
+        selector: article
+        attribute: id
+        regex: "article-([0-9]+)"
+"#;
+
+    const PAGE: &str = "<html><body><article id=\"article-42\">
+        <h1>  AAU opens
+            new campus </h1>
+        <time datetime=\"2019-12-01\">1 December</time>
+        <p class=\"tags\"><a href=\"/tags/campus\">campus</a><a href=\"/tags/cph\">cph</a></p>
+        </article></body></html>";
+
+    fn extract(rules: Rules, url: &str) -> (usize, Vec<Record>) {
+        let extractor = HTMLExtractorBase::new(RulesExtractor::new(rules, HTMLLinkExtractor::new()));
+        let response = Response::new(Url::parse(url).unwrap(), PAGE.as_bytes().to_vec());
+        let (links, records) = extractor.extract_content(response).unwrap();
+        (links.len(), records)
+    }
+
+    #[test]
+    fn extract_record() {
+        let (links, records) = extract(Rules::from_yaml(RULES).unwrap(), "https://www.aau.dk/news/campus");
+        assert_eq!(links, 2);
+
+        let mut fields = BTreeMap::new();
+        fields.insert(String::from("title"), FieldValue::Single(String::from("AAU opens new campus")));
+        fields.insert(String::from("published"), FieldValue::Single(String::from("2019-12-01")));
+        fields.insert(String::from("tags"), FieldValue::List(vec![String::from("campus"), String::from("cph")]));
+        fields.insert(String::from("id"), FieldValue::Single(String::from("42")));
+        assert_eq!(records, vec![Record {
+            url: String::from("https://www.aau.dk/news/campus"),
+            charset: Some(String::from("utf-8")),
+            rule: String::from("news"),
+            fields,
+        }]);
+        assert_eq!(
+            serde_json::to_string(&records[0].fields["tags"]).unwrap(),
+            r#"["campus","cph"]"#,
+        );
+    }
+
+    /// Rules only apply to pages whose Url matches, and rules files may be JSON
+    #[test]
+    fn rules_match_url() {
+        let (links, records) = extract(Rules::from_yaml(RULES).unwrap(), "https://www.aau.dk/about");
+        assert_eq!(links, 2);
+        assert!(records.is_empty());
+
+        let json = r#"{"rules": [{"name": "any", "url": ".*", "fields": {"missing": {"selector": "h2"}}}]}"#;
+        let (_, records) = extract(Rules::from_yaml(json).unwrap(), "https://www.aau.dk/about");
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(Rules::from_yaml("rules:\n  - name: a\n    url: '('\n    fields: {}").is_err());
+        assert!(Rules::from_yaml("rules:\n  - name: a\n    url: '.*'\n    fields:\n      b:\n        selector: '<<'").is_err());
+        assert!(Rules::from_yaml("rules:\n  - name: a\n    url: '.*'\n    fields:\n      b:\n        selector: p\n        cardinality: many").is_err());
+    }
+}
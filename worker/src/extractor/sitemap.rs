@@ -0,0 +1,317 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use url::Url;
+
+use crate::errors::{ExtractError, ExtractResult};
+use crate::errors::ExtractErrorKind::ParsingError;
+use crate::robots::rules::RobotsRules;
+use crate::task::{ChangeFrequency, SitemapInfo};
+use crate::traits::{Extractor, Link, Response};
+
+/// The first bytes of any gzip compressed file
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The SitemapExtractor is an Extractor that recognises sitemaps and extracts the Urls listed in
+/// them along with their lastmod, changefreq, and priority. XML sitemaps, sitemap indexes, and
+/// plain text sitemaps are supported, all of which may be compressed with gzip. Any other page is
+/// given to the inner Extractor.
+///
+/// Sitemaps are discovered by linking to /sitemap.xml and /robots.txt from the root page of each
+/// host. When a robots.txt is resolved, the sitemaps listed in it are extracted.
+pub struct SitemapExtractor<D> {
+    extractor: Box<dyn Extractor<Vec<u8>, D>>,
+    max_body_size: u64,
+}
+
+impl<D> SitemapExtractor<D> {
+    /// Construct a new SitemapExtractor that gives pages which are not sitemaps to the given
+    /// Extractor. Compressed pages that decompress to more than `max_body_size` bytes fail.
+    pub fn new(extractor: Box<dyn Extractor<Vec<u8>, D>>, max_body_size: u64) -> Self {
+        SitemapExtractor { extractor, max_body_size }
+    }
+}
+
+impl<D> Extractor<Vec<u8>, D> for SitemapExtractor<D> {
+    fn extract_content(&self, response: Response<Vec<u8>>) -> ExtractResult<(Vec<Link>, Vec<D>)> {
+        let response = if response.body.starts_with(&GZIP_MAGIC) {
+            let content = decompress(&response.body, self.max_body_size)?;
+            response.with_body(content)
+        } else {
+            response
+        };
+        let url = response.url.clone();
+        let content = &response.body;
+
+        if url.path() == "/robots.txt" {
+            let robots = String::from_utf8_lossy(content);
+            // Sitemaps are the same for every user agent
+            let links = RobotsRules::parse(&robots, "*").sitemaps()
+                .iter()
+                .filter_map(|sitemap| parse_http_url(sitemap))
+                .map(Link::new)
+                .collect();
+            return Ok((links, vec![]));
+        }
+
+        if let Some(links) = parse_xml_sitemap(content)? {
+            return Ok((links, vec![]));
+        }
+
+        if is_text_sitemap_url(&url) {
+            return Ok((parse_text_sitemap(content), vec![]));
+        }
+
+        let (mut links, data) = self.extractor.extract_content(response)?;
+        if url.path() == "/" {
+            links.extend(
+                ["/sitemap.xml", "/robots.txt"].iter()
+                    .filter_map(|path| url.join(path).ok())
+                    .map(Link::new)
+            );
+        }
+
+        Ok((links, data))
+    }
+}
+
+/// Decompress a gzip compressed page, but never to more than the maximum body size
+fn decompress(page: &[u8], max_body_size: u64) -> ExtractResult<Vec<u8>> {
+    let mut content = Vec::new();
+    GzDecoder::new(page).take(max_body_size.saturating_add(1)).read_to_end(&mut content).map_err(|e| {
+        ExtractError::new(ParsingError, "Failed to decompress page", Some(Box::new(e)))
+    })?;
+    if content.len() as u64 > max_body_size {
+        return Err(ExtractError::new(ParsingError, "Decompressed page is larger than the maximum body size", None));
+    }
+    Ok(content)
+}
+
+/// Parse an absolute http or https Url
+fn parse_http_url(url: &str) -> Option<Url> {
+    match Url::parse(url.trim()) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Some(url),
+        _ => None,
+    }
+}
+
+/// Plain text sitemaps cannot be recognised by their content, so they are recognised by having
+/// both "sitemap" and the .txt extension in their file name
+fn is_text_sitemap_url(url: &Url) -> bool {
+    let file_name = url.path().rsplit('/').next().unwrap_or("").to_lowercase();
+    file_name.contains("sitemap") && (file_name.ends_with(".txt") || file_name.ends_with(".txt.gz"))
+}
+
+/// Parse a plain text sitemap, which lists one Url per line
+fn parse_text_sitemap(content: &[u8]) -> Vec<Link> {
+    String::from_utf8_lossy(content)
+        .lines()
+        .filter_map(parse_http_url)
+        .map(Link::new)
+        .collect()
+}
+
+/// A <url> entry of a sitemap or a <sitemap> entry of a sitemap index, while being parsed
+#[derive(Default)]
+struct SitemapEntry {
+    loc: Option<String>,
+    info: SitemapInfo,
+}
+
+/// Parse an XML sitemap or sitemap index. Returns None if the content is not a sitemap, i.e. if
+/// the root element is not <urlset> or <sitemapindex>.
+fn parse_xml_sitemap(content: &[u8]) -> ExtractResult<Option<Vec<Link>>> {
+    let mut reader = Reader::from_reader(content);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut links = vec![];
+    let mut is_sitemap = false;
+    let mut entry: Option<SitemapEntry> = None;
+    // The local name of the innermost element
+    let mut element: Vec<u8> = vec![];
+
+    loop {
+        let text = match reader.read_event(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let name = e.local_name();
+                if !is_sitemap {
+                    if name != b"urlset" && name != b"sitemapindex" {
+                        return Ok(None);
+                    }
+                    is_sitemap = true;
+                } else if name == b"url" || name == b"sitemap" {
+                    entry = Some(SitemapEntry::default());
+                }
+                element = name.to_vec();
+                None
+            }
+            Ok(Event::End(e)) => {
+                let name = e.local_name();
+                if name == b"url" || name == b"sitemap" {
+                    if let Some(entry) = entry.take() {
+                        if let Some(url) = entry.loc.as_ref().and_then(|loc| parse_http_url(loc)) {
+                            let info = entry.info;
+                            let mut link = Link::new(url);
+                            if info != SitemapInfo::default() {
+                                link.sitemap = Some(info);
+                            }
+                            links.push(link);
+                        }
+                    }
+                }
+                element.clear();
+                None
+            }
+            Ok(Event::Text(e)) => Some(e.unescape_and_decode(&reader)),
+            Ok(Event::CData(e)) => Some(reader.decode(&e).map(|text| text.to_string())),
+            Ok(Event::Eof) => break,
+            // Content that is not even XML is not a sitemap
+            Err(_) if !is_sitemap => return Ok(None),
+            Err(e) => return Err(ExtractError::new(ParsingError, "Failed to parse sitemap", Some(Box::new(e)))),
+            _ => None,
+        };
+
+        if let (Some(text), Some(entry)) = (text, entry.as_mut()) {
+            let text = text.map_err(|e| {
+                ExtractError::new(ParsingError, "Failed to parse sitemap", Some(Box::new(e)))
+            })?;
+            match element.as_slice() {
+                b"loc" => entry.loc = Some(text),
+                b"lastmod" => entry.info.lastmod = Some(text),
+                b"changefreq" => entry.info.changefreq = ChangeFrequency::parse(&text),
+                b"priority" => entry.info.priority = text.trim().parse::<f32>().ok()
+                    .filter(|priority| *priority >= 0.0 && *priority <= 1.0),
+                _ => {}
+            }
+        }
+        buf.clear();
+    }
+
+    Ok(if is_sitemap { Some(links) } else { None })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use url::Url;
+
+    use crate::extractor::html::{HTMLExtractorBase, HTMLLinkExtractor};
+    use crate::extractor::sitemap::SitemapExtractor;
+    use crate::task::{ChangeFrequency, SitemapInfo};
+    use crate::traits::{Extractor, Link, Response};
+
+    fn extract(page: &[u8], url: &str) -> Vec<Link> {
+        let extractor = SitemapExtractor::new(Box::new(HTMLExtractorBase::new(HTMLLinkExtractor::new())), 1024);
+        let (links, _) = extractor.extract_content(Response::new(Url::parse(url).unwrap(), page.to_vec())).unwrap();
+        links
+    }
+
+    fn gzip(page: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(page).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn urls(links: &[Link]) -> Vec<&str> {
+        links.iter().map(|link| link.url.as_str()).collect()
+    }
+
+    const URLSET: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+        <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">
+            <url>
+                <loc>http://aau.dk/news?id=1&amp;lang=da</loc>
+                <lastmod>2019-11-20</lastmod>
+                <changefreq>daily</changefreq>
+                <priority>0.8</priority>
+            </url>
+            <url>
+                <loc>http://aau.dk/about</loc>
+            </url>
+            <url>
+                <loc>ftp://aau.dk/files</loc>
+            </url>
+        </urlset>";
+
+    #[test]
+    fn extract_urlset() {
+        let links = extract(URLSET.as_bytes(), "http://aau.dk/sitemap.xml");
+
+        assert_eq!(links, vec![
+            Link {
+                url: Url::parse("http://aau.dk/news?id=1&lang=da").unwrap(),
+                text: None,
+                source: None,
+                nofollow: false,
+                sitemap: Some(SitemapInfo {
+                    lastmod: Some(String::from("2019-11-20")),
+                    changefreq: Some(ChangeFrequency::Daily),
+                    priority: Some(0.8),
+                }),
+                priority: None,
+            },
+            Link::new(Url::parse("http://aau.dk/about").unwrap()),
+        ]);
+    }
+
+    #[test]
+    fn extract_gzip_urlset() {
+        let links = extract(&gzip(URLSET.as_bytes()), "http://aau.dk/sitemap.xml.gz");
+        assert_eq!(urls(&links), vec!["http://aau.dk/news?id=1&lang=da", "http://aau.dk/about"]);
+    }
+
+    /// Pages that decompress to more than the maximum body size fail, however small they are
+    #[test]
+    fn extract_gzip_over_max_body_size() {
+        let page = gzip(&[b' '; 1025]);
+        let extractor = SitemapExtractor::new(Box::new(HTMLExtractorBase::new(HTMLLinkExtractor::new())), 1024);
+        let response = Response::new(Url::parse("http://aau.dk/sitemap.xml.gz").unwrap(), page);
+        assert!(extractor.extract_content(response).is_err());
+    }
+
+    #[test]
+    fn extract_sitemap_index() {
+        let page = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+            <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">
+                <sitemap><loc>http://aau.dk/sitemap1.xml.gz</loc><lastmod>2019-10-01</lastmod></sitemap>
+                <sitemap><loc><![CDATA[http://aau.dk/sitemap2.xml]]></loc></sitemap>
+            </sitemapindex>";
+
+        let links = extract(page.as_bytes(), "http://aau.dk/sitemap.xml");
+        assert_eq!(urls(&links), vec!["http://aau.dk/sitemap1.xml.gz", "http://aau.dk/sitemap2.xml"]);
+        assert_eq!(links[0].sitemap.as_ref().unwrap().lastmod, Some(String::from("2019-10-01")));
+    }
+
+    #[test]
+    fn extract_text_sitemap() {
+        let page = "http://aau.dk/a\n\nhttp://aau.dk/b\nnot a url\n";
+
+        let links = extract(page.as_bytes(), "http://aau.dk/sitemap.txt");
+        assert_eq!(urls(&links), vec!["http://aau.dk/a", "http://aau.dk/b"]);
+    }
+
+    #[test]
+    fn extract_robots_sitemaps() {
+        let page = "User-agent: *\nDisallow: /private\nSitemap: http://aau.dk/sitemap_index.xml";
+
+        let links = extract(page.as_bytes(), "http://aau.dk/robots.txt");
+        assert_eq!(urls(&links), vec!["http://aau.dk/sitemap_index.xml"]);
+    }
+
+    /// Root pages link to the sitemap and robots.txt of the host, other pages do not
+    #[test]
+    fn extract_html_discovers_sitemaps() {
+        let page = "<!DOCTYPE html><html><body><a href=\"/page\">page</a></body></html>";
+
+        let links = extract(page.as_bytes(), "http://aau.dk/");
+        assert_eq!(urls(&links), vec!["http://aau.dk/page", "http://aau.dk/sitemap.xml", "http://aau.dk/robots.txt"]);
+
+        let links = extract(page.as_bytes(), "http://aau.dk/other");
+        assert_eq!(urls(&links), vec!["http://aau.dk/page"]);
+    }
+}
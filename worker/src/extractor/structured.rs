@@ -0,0 +1,399 @@
+use std::collections::BTreeMap;
+
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+use url::Url;
+
+use crate::downloader::parse_list;
+use crate::errors::ExtractResult;
+use crate::extractor::html::HTMLExtractor;
+use crate::extractor::rules::{FieldValue, Record};
+use crate::traits::{Link, Response};
+
+/// Prefixes of the meta tags of OpenGraph, its namespaces, and Twitter cards
+const META_PREFIXES: [&str; 8] = ["og:", "article:", "book:", "books:", "music:", "product:", "profile:", "twitter:"];
+
+/// A format of structured data embedded in pages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StructuredDataFormat {
+    /// <script type="application/ld+json"> blocks
+    JsonLd,
+    /// Items marked with itemscope, itemtype and itemprop attributes
+    Microdata,
+    /// Items marked with typeof and property attributes of RDFa Lite
+    Rdfa,
+    /// OpenGraph and Twitter card <meta> tags
+    OpenGraph,
+}
+
+impl StructuredDataFormat {
+    /// The name of the format, as given in configuration and as the rule of its records
+    pub fn name(self) -> &'static str {
+        match self {
+            StructuredDataFormat::JsonLd => "json-ld",
+            StructuredDataFormat::Microdata => "microdata",
+            StructuredDataFormat::Rdfa => "rdfa",
+            StructuredDataFormat::OpenGraph => "opengraph",
+        }
+    }
+}
+
+/// Parse a comma-separated list of structured data formats, where 'none' is no formats. Unknown
+/// names are a mistake in the configuration, so they cause a panic.
+pub fn parse_formats(list: &str) -> Vec<StructuredDataFormat> {
+    parse_list(list).iter()
+        .filter(|name| name.as_str() != "none")
+        .map(|name| match name.as_str() {
+            "json-ld" => StructuredDataFormat::JsonLd,
+            "microdata" => StructuredDataFormat::Microdata,
+            "rdfa" => StructuredDataFormat::Rdfa,
+            "opengraph" => StructuredDataFormat::OpenGraph,
+            _ => panic!("Unknown structured data format {:?}", name),
+        })
+        .collect()
+}
+
+/// The attributes that mark the items and properties of a format of structured data in HTML
+struct ItemAttributes {
+    scope: &'static str,
+    kind: &'static str,
+    property: &'static str,
+}
+
+const MICRODATA: ItemAttributes = ItemAttributes { scope: "itemscope", kind: "itemtype", property: "itemprop" };
+const RDFA: ItemAttributes = ItemAttributes { scope: "typeof", kind: "typeof", property: "property" };
+
+/// The StructuredDataExtractor is a HTMLExtractor that harvests the structured data embedded in
+/// pages, e.g. the schema.org descriptions of products, articles, and events, and has another
+/// HTMLExtractor extract the links and other data of the page.
+///
+/// Every item becomes a Record, where the rule is the name of the format. The properties of an
+/// item are normalised to fields named by their path, e.g. "offers.price" for the price of an
+/// offer of a product, with a field "type" of the type of the item. Schema.org types are
+/// shortened to their name, e.g. "Product". OpenGraph and Twitter card tags of a page become a
+/// single record with fields named by their property, e.g. "og:title".
+pub struct StructuredDataExtractor<H: HTMLExtractor<Record>> {
+    formats: Vec<StructuredDataFormat>,
+    extractor: H,
+    json_ld_selector: Selector,
+    microdata_selector: Selector,
+    rdfa_selector: Selector,
+    meta_selector: Selector,
+}
+
+impl<H: HTMLExtractor<Record>> StructuredDataExtractor<H> {
+    /// Construct a new StructuredDataExtractor that harvests the given formats, and extracts
+    /// links and other data with the given HTMLExtractor
+    pub fn new(formats: Vec<StructuredDataFormat>, extractor: H) -> Self {
+        StructuredDataExtractor {
+            formats,
+            extractor,
+            json_ld_selector: Selector::parse("script[type='application/ld+json']").expect("JSON-LD selector"),
+            // Items that are properties of other items are harvested along with those
+            microdata_selector: Selector::parse("[itemscope]:not([itemprop])").expect("microdata selector"),
+            rdfa_selector: Selector::parse("[typeof]:not([property])").expect("RDFa selector"),
+            meta_selector: Selector::parse("meta[content]").expect("meta tag selector"),
+        }
+    }
+
+    /// The fields of the items of the JSON-LD blocks of the page. Invalid blocks are skipped.
+    fn json_ld(&self, content: &Html) -> Vec<BTreeMap<String, Vec<String>>> {
+        let mut items = vec![];
+        for element in content.select(&self.json_ld_selector) {
+            let json = element.text().collect::<String>();
+            let value: Value = match serde_json::from_str(&json) {
+                Ok(value) => value,
+                Err(e) => {
+                    debug!("Skipped invalid JSON-LD. {}", e);
+                    continue;
+                }
+            };
+
+            // A block may hold a single item, a list of items, or a graph of items
+            let roots = match value {
+                Value::Array(roots) => roots,
+                Value::Object(mut object) => match object.remove("@graph") {
+                    Some(Value::Array(graph)) => graph,
+                    _ => vec![Value::Object(object)],
+                },
+                _ => vec![],
+            };
+            for root in roots {
+                let mut fields = BTreeMap::new();
+                flatten_json(&root, "", &mut fields);
+                items.push(fields);
+            }
+        }
+        items
+    }
+
+    /// The fields of the top-level microdata or RDFa items of the page
+    fn items(&self, content: &Html, selector: &Selector, attributes: &ItemAttributes, url: &Url) -> Vec<BTreeMap<String, Vec<String>>> {
+        content.select(selector)
+            .map(|item| {
+                let mut fields = BTreeMap::new();
+                item_properties(item, attributes, "", url, &mut fields);
+                fields
+            })
+            .collect()
+    }
+
+    /// The fields of the OpenGraph and Twitter card tags of the page, as a single item
+    fn meta_tags(&self, content: &Html) -> Vec<BTreeMap<String, Vec<String>>> {
+        let mut fields: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for element in content.select(&self.meta_selector) {
+            // OpenGraph uses the property attribute, and Twitter cards the name attribute
+            let key = element.value().attr("property")
+                .or_else(|| element.value().attr("name"))
+                .unwrap_or("")
+                .to_ascii_lowercase();
+            if META_PREFIXES.iter().any(|prefix| key.starts_with(prefix)) {
+                let value = element.value().attr("content").unwrap_or("").trim().to_string();
+                fields.entry(key).or_default().push(value);
+            }
+        }
+        vec![fields]
+    }
+}
+
+impl<H: HTMLExtractor<Record>> HTMLExtractor<Record> for StructuredDataExtractor<H> {
+    fn extract_from_html(&self, content: Html, response: &Response<String>) -> ExtractResult<(Vec<Link>, Vec<Record>)> {
+        let mut records = vec![];
+        for format in self.formats.iter() {
+            let items = match format {
+                StructuredDataFormat::JsonLd => self.json_ld(&content),
+                StructuredDataFormat::Microdata => self.items(&content, &self.microdata_selector, &MICRODATA, &response.url),
+                StructuredDataFormat::Rdfa => self.items(&content, &self.rdfa_selector, &RDFA, &response.url),
+                StructuredDataFormat::OpenGraph => self.meta_tags(&content),
+            };
+            records.extend(items.into_iter()
+                .filter(|fields| !fields.is_empty())
+                .map(|fields| Record {
+                    url: response.url.to_string(),
+                    charset: response.charset.clone(),
+                    rule: String::from(format.name()),
+                    fields: fields.into_iter()
+                        .map(|(name, mut values)| {
+                            let value = if values.len() == 1 {
+                                FieldValue::Single(values.remove(0))
+                            } else {
+                                FieldValue::List(values)
+                            };
+                            (name, value)
+                        })
+                        .collect(),
+                }));
+        }
+
+        let (links, data) = self.extractor.extract_from_html(content, response)?;
+        records.extend(data);
+        Ok((links, records))
+    }
+}
+
+/// The path of a property of an item at the given path
+fn join_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        String::from(name)
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+/// Shorten a schema.org type to its name, e.g. "https://schema.org/Product" to "Product"
+fn normalise_type(kind: &str) -> String {
+    let kind = kind.trim();
+    let url = kind.trim_start_matches("https://").trim_start_matches("http://");
+    let name = url.trim_start_matches("schema.org/");
+    if name.len() < url.len() {
+        String::from(name)
+    } else {
+        String::from(kind)
+    }
+}
+
+/// Add the values of a JSON-LD item to the fields by their path. The keywords @type and @id
+/// become the fields "type" and "id", and other keywords, e.g. @context, are skipped.
+fn flatten_json(value: &Value, path: &str, fields: &mut BTreeMap<String, Vec<String>>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter() {
+                let name = match key.as_str() {
+                    "@type" => "type",
+                    "@id" => "id",
+                    _ if key.starts_with('@') => continue,
+                    _ => key.as_str(),
+                };
+                flatten_json(value, &join_path(path, name), fields);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                flatten_json(value, path, fields);
+            }
+        }
+        Value::String(value) => {
+            let value = if path == "type" || path.ends_with(".type") { normalise_type(value) } else { value.clone() };
+            fields.entry(String::from(path)).or_default().push(value);
+        }
+        Value::Number(value) => fields.entry(String::from(path)).or_default().push(value.to_string()),
+        Value::Bool(value) => fields.entry(String::from(path)).or_default().push(value.to_string()),
+        Value::Null => {}
+    }
+}
+
+/// Add the type and properties of a microdata or RDFa item to the fields by their path. The
+/// properties of an item are the elements with a property attribute whose closest item is this
+/// item, and properties that are items themselves are added under their own path.
+fn item_properties(item: ElementRef, attributes: &ItemAttributes, path: &str, url: &Url, fields: &mut BTreeMap<String, Vec<String>>) {
+    if let Some(kind) = item.value().attr(attributes.kind) {
+        for kind in kind.split_whitespace() {
+            fields.entry(join_path(path, "type")).or_default().push(normalise_type(kind));
+        }
+    }
+
+    let properties = item.descendants()
+        .skip(1)
+        .filter_map(ElementRef::wrap)
+        .filter(|element| element.value().attr(attributes.property).is_some())
+        .filter(|element| {
+            element.ancestors()
+                .filter_map(ElementRef::wrap)
+                .find(|ancestor| ancestor.value().attr(attributes.scope).is_some())
+                .map(|ancestor| ancestor.id())
+                == Some(item.id())
+        });
+
+    for element in properties {
+        for name in element.value().attr(attributes.property).unwrap_or("").split_whitespace() {
+            let property_path = join_path(path, name);
+            if element.value().attr(attributes.scope).is_some() {
+                item_properties(element, attributes, &property_path, url, fields);
+            } else {
+                fields.entry(property_path).or_default().push(property_value(element, url));
+            }
+        }
+    }
+}
+
+/// The value of a microdata or RDFa property, which is given by the content attribute, the
+/// Url an element links to, the machine-readable value of an element, or else its text
+fn property_value(element: ElementRef, url: &Url) -> String {
+    let value = element.value();
+    if let Some(content) = value.attr("content") {
+        return content.trim().to_string();
+    }
+
+    let link = match value.name() {
+        "a" | "area" | "link" => value.attr("href"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => value.attr("src"),
+        "object" => value.attr("data"),
+        _ => value.attr("resource"),
+    };
+    if let Some(link) = link {
+        return url.join(link.trim()).map(String::from).unwrap_or_else(|_| link.trim().to_string());
+    }
+
+    let machine_readable = match value.name() {
+        "data" | "meter" => value.attr("value"),
+        "time" => value.attr("datetime"),
+        _ => None,
+    };
+    match machine_readable {
+        Some(value) => value.trim().to_string(),
+        None => element.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::extractor::html::{HTMLExtractorBase, HTMLLinkExtractor};
+    use crate::extractor::rules::{FieldValue, Record, Rules, RulesExtractor};
+    use crate::extractor::structured::{parse_formats, StructuredDataExtractor, StructuredDataFormat};
+    use crate::traits::{Extractor, Response};
+
+    fn extract(formats: &str, page: &str) -> Vec<Record> {
+        let rules = RulesExtractor::new(Rules::default(), HTMLLinkExtractor::new());
+        let extractor = HTMLExtractorBase::new(StructuredDataExtractor::new(parse_formats(formats), rules));
+        let response = Response::new(Url::parse("https://shop.dk/products/1").unwrap(), page.as_bytes().to_vec());
+        extractor.extract_content(response).unwrap().1
+    }
+
+    fn field<'a>(record: &'a Record, name: &str) -> &'a FieldValue {
+        &record.fields[name]
+    }
+
+    fn single(value: &str) -> FieldValue {
+        FieldValue::Single(String::from(value))
+    }
+
+    #[test]
+    fn extract_json_ld() {
+        let page = r#"<html><head><script type="application/ld+json">
+            {"@context": "https://schema.org", "@type": "Product", "name": "Kettle",
+             "offers": [{"@type": "Offer", "price": 29.95}, {"@type": "Offer", "price": 24.95}]}
+            </script><script type="application/ld+json">{"@graph": [{"@type": "Event", "name": "Sale"}]}</script>
+            <script type="application/ld+json">{ invalid </script></head></html>"#;
+
+        let records = extract("json-ld", page);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].rule, "json-ld");
+        assert_eq!(records[0].url, "https://shop.dk/products/1");
+        assert_eq!(field(&records[0], "type"), &single("Product"));
+        assert_eq!(field(&records[0], "offers.type"), &FieldValue::List(vec![String::from("Offer"), String::from("Offer")]));
+        assert_eq!(field(&records[0], "offers.price"), &FieldValue::List(vec![String::from("29.95"), String::from("24.95")]));
+        assert!(!records[0].fields.contains_key("context"));
+        assert_eq!(field(&records[1], "name"), &single("Sale"));
+    }
+
+    /// Nested items are properties of their item rather than items of their own
+    #[test]
+    fn extract_microdata_and_rdfa() {
+        let page = r#"<div itemscope itemtype="https://schema.org/Product">
+            <h1 itemprop="name">Kettle</h1>
+            <img itemprop="image" src="/kettle.jpg">
+            <div itemprop="offers" itemscope itemtype="http://schema.org/Offer">
+              <meta itemprop="priceCurrency" content="DKK"><span itemprop="price">29.95</span>
+            </div>
+            </div>
+            <div vocab="https://schema.org/" typeof="Event">
+              <span property="name">Sale</span><time property="startDate" datetime="2019-12-24">Christmas</time>
+            </div>"#;
+
+        let records = extract("microdata, rdfa", page);
+        assert_eq!(records.len(), 2);
+        let product = &records[0];
+        assert_eq!(product.rule, "microdata");
+        assert_eq!(field(product, "type"), &single("Product"));
+        assert_eq!(field(product, "name"), &single("Kettle"));
+        assert_eq!(field(product, "image"), &single("https://shop.dk/kettle.jpg"));
+        assert_eq!(field(product, "offers.type"), &single("Offer"));
+        assert_eq!(field(product, "offers.price"), &single("29.95"));
+        assert_eq!(field(product, "offers.priceCurrency"), &single("DKK"));
+        assert!(!product.fields.contains_key("price"));
+
+        let event = &records[1];
+        assert_eq!(event.rule, "rdfa");
+        assert_eq!(field(event, "type"), &single("Event"));
+        assert_eq!(field(event, "startDate"), &single("2019-12-24"));
+    }
+
+    #[test]
+    fn extract_opengraph() {
+        let page = r#"<head><meta property="og:title" content="Kettle">
+            <meta property="og:image" content="https://shop.dk/1.jpg"><meta property="og:image" content="https://shop.dk/2.jpg">
+            <meta name="twitter:card" content="summary"><meta name="description" content="A kettle"></head>"#;
+
+        let records = extract("opengraph", page);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].fields.len(), 3);
+        assert_eq!(field(&records[0], "og:title"), &single("Kettle"));
+        assert_eq!(field(&records[0], "twitter:card"), &single("summary"));
+
+        // Only the given formats are harvested
+        assert!(extract("none", page).is_empty());
+        assert_eq!(parse_formats("JSON-LD,opengraph"), vec![StructuredDataFormat::JsonLd, StructuredDataFormat::OpenGraph]);
+    }
+}
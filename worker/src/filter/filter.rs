@@ -149,10 +149,10 @@ mod tests {
     #[test]
     fn whitelist_test_01() {
         let whitelist = Whitelist::new_from_vec(get_predefined_list());
-        let task = task::Task { url: Url::parse("http://reddit.com").unwrap() };
+        let task = task::Task::new(Url::parse("http://reddit.com").unwrap());
         let tasks: Vec<Task> = vec![task];
 
-        let expected = task::Task { url: Url::parse("http://reddit.com").unwrap() };
+        let expected = task::Task::new(Url::parse("http://reddit.com").unwrap());
         assert!(whitelist.filter(tasks).contains(&expected))
     }
 
@@ -161,10 +161,10 @@ mod tests {
     #[test]
     fn whitelist_test_02() {
         let whitelist = Whitelist::new_from_vec(get_predefined_list());
-        let task = task::Task { url: Url::parse("http://tv2.dk").unwrap() };
+        let task = task::Task::new(Url::parse("http://tv2.dk").unwrap());
         let tasks: Vec<Task> = vec![task];
 
-        let expected = task::Task { url: Url::parse("http://tv2.dk").unwrap() };
+        let expected = task::Task::new(Url::parse("http://tv2.dk").unwrap());
         assert!(!whitelist.filter(tasks).contains(&expected))
     }
 
@@ -173,13 +173,13 @@ mod tests {
     #[test]
     fn whitelist_test_03() {
         let whitelist = Whitelist::new_from_vec(get_predefined_list());
-        let task = task::Task { url: Url::parse("http://bbc.co.uk").unwrap() };
-        let task1 = task::Task { url: Url::parse("http://okboomer.dk").unwrap() };
+        let task = task::Task::new(Url::parse("http://bbc.co.uk").unwrap());
+        let task1 = task::Task::new(Url::parse("http://okboomer.dk").unwrap());
         let tasks: Vec<Task> = vec![task, task1];
         let filtered_tasks = whitelist.filter(tasks);
 
-        let task = task::Task { url: Url::parse("http://bbc.co.uk").unwrap() };
-        let task1 = task::Task { url: Url::parse("http://okboomer.dk").unwrap() };
+        let task = task::Task::new(Url::parse("http://bbc.co.uk").unwrap());
+        let task1 = task::Task::new(Url::parse("http://okboomer.dk").unwrap());
 
         assert!(filtered_tasks.contains(&task));
         assert!(!filtered_tasks.contains(&task1));
@@ -190,10 +190,10 @@ mod tests {
     #[test]
     fn blacklist_test_01() {
         let blacklist = Blacklist::new_from_vec(get_predefined_list());
-        let task = task::Task { url: Url::parse("http://reddit.com").unwrap() };
+        let task = task::Task::new(Url::parse("http://reddit.com").unwrap());
         let tasks: Vec<Task> = vec![task];
 
-        let expected = task::Task { url: Url::parse("http://reddit.com").unwrap() };
+        let expected = task::Task::new(Url::parse("http://reddit.com").unwrap());
         assert!(!blacklist.filter(tasks).contains(&expected))
     }
 
@@ -202,10 +202,10 @@ mod tests {
     #[test]
     fn blacklist_test_02() {
         let blacklist = Blacklist::new_from_vec(get_predefined_list());
-        let task = task::Task { url: Url::parse("http://tv2.dk").unwrap() };
+        let task = task::Task::new(Url::parse("http://tv2.dk").unwrap());
         let tasks: Vec<Task> = vec![task];
 
-        let expected = task::Task { url: Url::parse("http://tv2.dk").unwrap() };
+        let expected = task::Task::new(Url::parse("http://tv2.dk").unwrap());
         assert!(blacklist.filter(tasks).contains(&expected))
     }
 
@@ -214,12 +214,12 @@ mod tests {
     #[test]
     fn blacklist_test_03() {
         let blacklist = Blacklist::new_from_vec(get_predefined_list());
-        let task = task::Task { url: Url::parse("http://reddit.com").unwrap() };
-        let task1 = task::Task { url: Url::parse("http://okboomer.dk").unwrap() };
+        let task = task::Task::new(Url::parse("http://reddit.com").unwrap());
+        let task1 = task::Task::new(Url::parse("http://okboomer.dk").unwrap());
         let tasks: Vec<Task> = vec![task, task1];
 
-        let task = task::Task { url: Url::parse("http://reddit.com").unwrap() };
-        let task1 = task::Task { url: Url::parse("http://okboomer.dk").unwrap() };
+        let task = task::Task::new(Url::parse("http://reddit.com").unwrap());
+        let task1 = task::Task::new(Url::parse("http://okboomer.dk").unwrap());
 
         let filtered_tasks = blacklist.filter(tasks);
 
@@ -232,10 +232,10 @@ mod tests {
     fn nofilter_test_01() {
         let filter = NoFilter;
 
-        let task = task::Task { url: Url::parse("http://tv2.dk").unwrap() };
+        let task = task::Task::new(Url::parse("http://tv2.dk").unwrap());
         let tasks: Vec<Task> = vec![task];
 
-        let expected = task::Task { url: Url::parse("http://tv2.dk").unwrap() };
+        let expected = task::Task::new(Url::parse("http://tv2.dk").unwrap());
         assert!(filter.filter(tasks).contains(&expected))
     }
 
@@ -244,15 +244,15 @@ mod tests {
     fn nofilter_test_02() {
         let filter = NoFilter;
 
-        let task1 = task::Task { url: Url::parse("http://bbc.co.uk").unwrap() };
-        let task3 = task::Task { url: Url::parse("http://dr.dk").unwrap() };
-        let task2 = task::Task { url: Url::parse("http://okboomer.dk").unwrap() };
-        let task4 = task::Task { url: Url::parse("http://facebook.com").unwrap() };
+        let task1 = task::Task::new(Url::parse("http://bbc.co.uk").unwrap());
+        let task3 = task::Task::new(Url::parse("http://dr.dk").unwrap());
+        let task2 = task::Task::new(Url::parse("http://okboomer.dk").unwrap());
+        let task4 = task::Task::new(Url::parse("http://facebook.com").unwrap());
         let tasks: Vec<Task> = vec![task1, task2, task3, task4];
 
         let mut tasks_clone: Vec<Task> = Vec::new();
         for i in &tasks {
-            tasks_clone.push(Task { url: Url::parse(i.url.as_str()).unwrap() })
+            tasks_clone.push(Task::new(Url::parse(i.url.as_str()).unwrap()))
         }
 
         assert_eq!(filter.filter(tasks), tasks_clone)
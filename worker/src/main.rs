@@ -1,4 +1,5 @@
 extern crate clap;
+extern crate crossbeam;
 extern crate futures;
 extern crate lapin_futures;
 #[macro_use]
@@ -13,35 +14,61 @@ extern crate influx_db_client;
 
 use std::error::Error;
 use std::io::ErrorKind;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use log::LevelFilter;
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
+use redis::IntoConnectionInfo;
 
+use crate::archive::file::FileArchive;
 use crate::defaultnormaliser::DefaultNormaliser;
-use crate::downloader::DefaultDownloader;
-use crate::extractor::html::{HTMLExtractorBase, HTMLLinkExtractor};
+use crate::credentials::Secrets;
+use crate::cache::{CacheMode, CachingDownloader};
+use crate::downloader::{DefaultDownloader, DownloaderConfig, DEFAULT_ALLOWED_MEDIA_TYPES, DEFAULT_HEAD_EXTENSIONS, parse_list};
+use crate::extractor::directives::{DirectivePolicy, NofollowMode};
+use crate::extractor::html::{DEFAULT_LINK_SOURCES, HTMLExtractorBase, HTMLLinkExtractor, parse_link_sources};
+use crate::extractor::rules::{Record, Rules, RulesExtractor};
+use crate::extractor::sitemap::SitemapExtractor;
+use crate::extractor::structured::{parse_formats, StructuredDataExtractor};
 use crate::filter::filter::{Blacklist, NoFilter, Whitelist};
-use crate::metrics::influx_client::{InfluxClient, InfluxCredentials, get_timestamp_millis};
-use crate::rmqredis::RMQRedisManager;
+use crate::metrics::influx_client::{InfluxClient, InfluxCredentials, get_timestamp_millis, write_proxy_health};
+use crate::proxypool::PROXY_HEALTH_INTERVAL;
+use crate::prioritiser::opic::OPICPrioritiser;
+use crate::recrawl::scheduler::{NoScheduler, RecrawlPolicy, RedisScheduler};
+use crate::recrawl::validators::RedisValidatorStore;
+use crate::prioritiser::simple::{DepthPrioritiser, KeywordPrioritiser, NoPrioritiser};
+use crate::politeness::limiter::{BusyHostMode, PoliteDownloader, read_overrides_from_file, RedisRateLimiter};
+use crate::rmqredis::{create_redis_connection, DeadLetter, RetryPolicy, RMQRedisManager, RMQRedisManagerConfig};
+use crate::robots::filter::RobotsFilter;
 use crate::task::Task;
-use crate::traits::Filter;
+use crate::traits::{Archive, Downloader, Extractor, Filter, Manager, Prioritiser, Scheduler};
 use crate::void::Void;
-use crate::worker::Worker;
+use crate::worker::{Worker, WorkerComponents};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
 
 mod archive;
+mod cache;
+mod credentials;
 mod defaultnormaliser;
 mod downloader;
 mod errors;
 mod extractor;
 mod filter;
 mod metrics;
+mod partition;
+mod politeness;
+mod prioritiser;
+mod proxypool;
+mod recrawl;
 mod rmqredis;
+mod robots;
 mod split;
 mod task;
 mod traits;
@@ -75,6 +102,186 @@ fn get_log4rs_config(log_path: &str, default_log_level: LevelFilter) -> log4rs::
         .unwrap()
 }
 
+/// Run the dead-letter subcommand, which either lists the entries of the dead-letter queue or
+/// moves selected entries back to the frontier
+fn run_dead_letter_command(manager: &RMQRedisManager, args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    match args.subcommand() {
+        ("list", Some(list_args)) => {
+            let limit = list_args.value_of("limit").unwrap().parse().expect("Failed parsing limit to usize");
+            let dead_letters = manager.get_dead_letters(limit)?;
+            for dead_letter in dead_letters.iter() {
+                println!("{}", format_dead_letter(dead_letter));
+            }
+            println!("Listed {} entries of the dead-letter queue", dead_letters.len());
+        }
+        ("requeue", Some(requeue_args)) => {
+            let limit = requeue_args.value_of("limit").unwrap().parse().expect("Failed parsing limit to usize");
+            let error_kind = requeue_args.value_of("error-kind");
+            let url_contains = requeue_args.value_of("url-contains");
+            let requeued = manager.requeue_dead_letters(&|dead_letter| {
+                // Entries must match every filter that was given
+                let kind_matches = match (error_kind, &dead_letter.error_kind) {
+                    (Some(kind), Some(dead_letter_kind)) => kind == dead_letter_kind,
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                };
+                let url_matches = match url_contains {
+                    Some(s) => String::from_utf8_lossy(&dead_letter.data).contains(s),
+                    None => true,
+                };
+                kind_matches && url_matches
+            }, limit)?;
+            println!("Moved {} entries from the dead-letter queue to the frontier", requeued);
+        }
+        _ => println!("{}", args.usage()),
+    }
+
+    Ok(())
+}
+
+/// Run the drain subcommand, which moves the tasks of another queue into the partitions of the
+/// frontier
+fn run_drain_command(manager: &RMQRedisManager, args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let queue = args.value_of("queue").unwrap();
+    if manager.partition_queue_names().iter().any(|name| name == queue) {
+        return Err(format!("Queue {:?} is a partition of the frontier", queue).into());
+    }
+    let drained = manager.drain_queue(queue)?;
+    println!("Moved {} tasks from {} to the partitions of the frontier", drained, queue);
+    Ok(())
+}
+
+/// Run the recrawl subcommand, which submits the pages that are due to be recrawled to the
+/// frontier as they become due. This is a blocking operation.
+fn run_recrawl_command(manager: &RMQRedisManager, scheduler: &RedisScheduler, args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let limit = args.value_of("limit").unwrap().parse().expect("Failed parsing limit to usize");
+    let poll_interval = args.value_of("poll-interval").unwrap().parse().expect("Failed parsing poll interval to u64");
+    info!("Recrawl scheduler has started");
+
+    loop {
+        let tasks = scheduler.take_due(limit)?;
+        let count = tasks.len();
+        if count > 0 {
+            if let Err(e) = manager.submit(tasks.clone()) {
+                // The tasks are no longer in the recrawl set, so they are lost unless given back
+                scheduler.restore(&tasks)?;
+                return Err(Box::new(e));
+            }
+            info!("Submitted {} pages for recrawling", count);
+        }
+
+        // Keep submitting while there are more due pages than the limit
+        if count < limit {
+            thread::sleep(Duration::from_secs(poll_interval));
+        }
+    }
+}
+
+/// The RetryPolicy given by the arguments
+fn retry_policy(args: &ArgMatches) -> Result<RetryPolicy, Box<dyn Error>> {
+    let policy = RetryPolicy {
+        max_attempts: args.value_of("max-attempts").unwrap().parse().expect("Failed parsing max attempts to u32"),
+        base_delay_ms: args.value_of("retry-delay").unwrap().parse().expect("Failed parsing retry delay to u64"),
+        max_retry_after_ms: args.value_of("max-retry-after").unwrap().parse().expect("Failed parsing max Retry-After to u64"),
+    };
+    policy.validate()?;
+    Ok(policy)
+}
+
+/// The RecrawlPolicy given by the arguments
+fn recrawl_policy(args: &ArgMatches) -> RecrawlPolicy {
+    let seconds = |name: &str| -> u64 {
+        args.value_of(name).unwrap().parse::<u64>().expect("Failed parsing recrawl interval to u64") * 1000
+    };
+    RecrawlPolicy {
+        default_interval_ms: seconds("recrawl-interval"),
+        min_interval_ms: seconds("recrawl-min-interval"),
+        max_interval_ms: seconds("recrawl-max-interval"),
+    }
+}
+
+/// The DownloaderConfig given by the arguments and the downloader config file
+fn downloader_config(args: &ArgMatches) -> Result<DownloaderConfig, Box<dyn Error>> {
+    let mut config = DownloaderConfig {
+        user_agent: args.value_of("user-agent").unwrap().to_string(),
+        timeout_secs: args.value_of("download-timeout").unwrap().parse().expect("Failed parsing download timeout to u64"),
+        connect_timeout_secs: args.value_of("connect-timeout").unwrap().parse().expect("Failed parsing connect timeout to u64"),
+        max_redirects: args.value_of("max-redirects").unwrap().parse().expect("Failed parsing max redirects to usize"),
+        max_body_size: args.value_of("max-body-size").unwrap().parse().expect("Failed parsing max body size to u64"),
+        allowed_media_types: parse_list(args.value_of("allowed-media-types").unwrap()),
+        head_extensions: parse_list(args.value_of("head-extensions").unwrap()),
+        cookies: args.value_of("cookies-enable").unwrap().parse().expect("Failed parsing cookies enable to bool"),
+        ..DownloaderConfig::default()
+    };
+
+    for header in args.values_of("header").into_iter().flatten() {
+        let mut parts = header.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) => {
+                config.headers.insert(name.trim().to_string(), value.trim().to_string());
+            }
+            _ => panic!("Header is not of the form 'NAME: VALUE': {:?}", header),
+        }
+    }
+
+    match args.value_of("secrets-file").unwrap() {
+        "none" => {}
+        path => config.credentials = Secrets::from_file(path)?.hosts,
+    }
+
+    match args.value_of("downloader-config").unwrap() {
+        "none" => {}
+        path => config.apply_file(path)?,
+    }
+
+    Ok(config)
+}
+
+/// Construct the client of the metrics database, if metrics are enabled
+fn influx_client(args: &ArgMatches) -> Option<InfluxClient> {
+    let influx_credentials = if args.value_of("influx-authenticate").unwrap().parse().unwrap() {
+        Some(InfluxCredentials {
+            username: args.value_of("influx-username").unwrap().to_string(),
+            password: args.value_of("influx-password").unwrap().to_string(),
+        })
+    } else {
+        None
+    };
+
+    if args
+        .value_of("metrics-enable")
+        .unwrap()
+        .parse()
+        .expect("The 'metrics-enable' argument was not a boolean")
+    {
+        Some(InfluxClient::new(
+            args.value_of("influx-addr").unwrap(),
+            args.value_of("influx-port")
+                .unwrap()
+                .parse()
+                .expect("The 'influx-port' argument was not an int"),
+            influx_credentials,
+            args.value_of("influx-database").unwrap(),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Format a dead-letter entry as a single line
+fn format_dead_letter(dead_letter: &DeadLetter) -> String {
+    let unknown = String::from("unknown");
+    format!(
+        "{} [{}] {} (worker: {}, attempts: {}, time: {})",
+        String::from_utf8_lossy(&dead_letter.data),
+        dead_letter.error_kind.as_ref().unwrap_or(&unknown),
+        dead_letter.error_message.as_ref().unwrap_or(&unknown),
+        dead_letter.worker.as_ref().unwrap_or(&unknown),
+        dead_letter.attempts,
+        dead_letter.timestamp.map_or(unknown.clone(), |t| t.to_string()),
+    )
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     // Set up arguments and get resulting arguments
     let args = App::new("DatScraper Worker")
@@ -130,6 +337,34 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .default_value("5")
                 .value_name("COUNT")
                 .help("Specify the number of tasks to prefetch")
+        ).arg(
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .env("SCRAPER_CONCURRENCY")
+                .default_value("5")
+                .value_name("COUNT")
+                .help("Specify the number of tasks to resolve concurrently")
+        ).arg(
+            Arg::with_name("max-attempts")
+                .long("max-attempts")
+                .env("SCRAPER_MAX_ATTEMPTS")
+                .default_value("5")
+                .value_name("COUNT")
+                .help("Specify the number of times a rejected task is attempted before it is dropped")
+        ).arg(
+            Arg::with_name("retry-delay")
+                .long("retry-delay")
+                .env("SCRAPER_RETRY_DELAY")
+                .default_value("5000")
+                .value_name("MILLISECONDS")
+                .help("Specify the delay before the first retry of a rejected task. The delay doubles with every retry")
+        ).arg(
+            Arg::with_name("max-retry-after")
+                .long("max-retry-after")
+                .env("SCRAPER_MAX_RETRY_AFTER")
+                .default_value("3600000")
+                .value_name("MS")
+                .help("Specify the longest Retry-After to honour. Rejected tasks asking for a longer delay are retried as if they did not ask")
         ).arg(
             Arg::with_name("rabbitmq-queue")
                 .short("q")
@@ -146,6 +381,20 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .default_value("collection")
                 .value_name("COLLECTION")
                 .help("Specify the RabbitMQ collection queue to connect to")
+        ).arg(
+            Arg::with_name("rabbitmq-dead-letter-queue")
+                .long("rmq-dead-letter-queue")
+                .env("SCRAPER_RABBITMQ_DEAD_LETTER_QUEUE")
+                .default_value("dead-letter")
+                .value_name("QUEUE")
+                .help("Specify the RabbitMQ queue that receives tasks which failed permanently")
+        ).arg(
+            Arg::with_name("partitions")
+                .long("partitions")
+                .env("SCRAPER_PARTITIONS")
+                .default_value("16")
+                .value_name("NUMBER")
+                .help("Specify the number of partitions of the frontier queue. Tasks are partitioned by host")
         ).arg(
             Arg::with_name("sentinel")
                 .short("m")
@@ -202,6 +451,253 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .default_value("white")
                 .value_name("STRING")
                 .help("Specify whether the list in the given filter-path is a 'white' or 'black'-list")
+        ).arg(
+            Arg::with_name("politeness-enable")
+                .long("politeness-enable")
+                .env("SCRAPER_POLITENESS_ENABLE")
+                .default_value("true")
+                .value_name("BOOLEAN")
+                .help("Specify whether to limit how often each host is visited across all workers")
+        ).arg(
+            Arg::with_name("politeness-delay")
+                .long("politeness-delay")
+                .env("SCRAPER_POLITENESS_DELAY")
+                .default_value("1000")
+                .value_name("MS")
+                .help("Specify the default delay between visits to the same host")
+        ).arg(
+            Arg::with_name("politeness-path")
+                .long("politeness-path")
+                .env("SCRAPER_POLITENESS_PATH")
+                .default_value("src/politeness/overrides.txt")
+                .value_name("PATH")
+                .help("Specify path to a list of per-host delays, each line being a host and a delay in milliseconds")
+        ).arg(
+            Arg::with_name("politeness-per-ip")
+                .long("politeness-per-ip")
+                .env("SCRAPER_POLITENESS_PER_IP")
+                .default_value("false")
+                .value_name("BOOLEAN")
+                .help("Specify whether hosts that resolve to the same IP address share their delay")
+        ).arg(
+            Arg::with_name("politeness-mode")
+                .long("politeness-mode")
+                .env("SCRAPER_POLITENESS_MODE")
+                .default_value("wait")
+                .value_name("STRING")
+                .help("Specify whether to 'wait' for a host that was visited too recently or to 'requeue' the task")
+        ).arg(
+            Arg::with_name("user-agent")
+                .long("user-agent")
+                .env("SCRAPER_USER_AGENT")
+                .default_value("DatScraper/0.1")
+                .value_name("STRING")
+                .help("Specify the user agent of the worker")
+        ).arg(
+            Arg::with_name("download-timeout")
+                .long("download-timeout")
+                .env("SCRAPER_DOWNLOAD_TIMEOUT")
+                .default_value("30")
+                .value_name("SECONDS")
+                .help("Specify how long to wait for a page to download")
+        ).arg(
+            Arg::with_name("connect-timeout")
+                .long("connect-timeout")
+                .env("SCRAPER_CONNECT_TIMEOUT")
+                .default_value("10")
+                .value_name("SECONDS")
+                .help("Specify how long to wait for a connection to a host")
+        ).arg(
+            Arg::with_name("max-redirects")
+                .long("max-redirects")
+                .env("SCRAPER_MAX_REDIRECTS")
+                .default_value("10")
+                .value_name("NUMBER")
+                .help("Specify the maximum number of redirects to follow when downloading a page. With 0, redirect targets are submitted as new tasks")
+        ).arg(
+            Arg::with_name("max-body-size")
+                .long("max-body-size")
+                .env("SCRAPER_MAX_BODY_SIZE")
+                .default_value("10485760")
+                .value_name("BYTES")
+                .help("Specify the size of the largest page to download, or sitemap to decompress")
+        ).arg(
+            Arg::with_name("allowed-media-types")
+                .long("allowed-media-types")
+                .env("SCRAPER_ALLOWED_MEDIA_TYPES")
+                .default_value(DEFAULT_ALLOWED_MEDIA_TYPES)
+                .value_name("LIST")
+                .help("Specify a comma-separated list of the media types to download, e.g. 'text/html' or 'text/*'. Pages of other media types are skipped. Every page is downloaded if empty")
+        ).arg(
+            Arg::with_name("head-extensions")
+                .long("head-extensions")
+                .env("SCRAPER_HEAD_EXTENSIONS")
+                .default_value(DEFAULT_HEAD_EXTENSIONS)
+                .value_name("LIST")
+                .help("Specify a comma-separated list of Url extensions whose media type is checked before downloading, with a HEAD request if the extension is unknown")
+        ).arg(
+            Arg::with_name("header")
+                .long("header")
+                .env("SCRAPER_HEADERS")
+                .multiple(true)
+                .number_of_values(1)
+                .value_delimiter("\n")
+                .value_name("NAME: VALUE")
+                .help("Specify a header to send with every request. May be given several times, or separated by newlines")
+        ).arg(
+            Arg::with_name("downloader-config")
+                .long("downloader-config")
+                .env("SCRAPER_DOWNLOADER_CONFIG")
+                .default_value("none")
+                .value_name("PATH")
+                .help("Specify a YAML file of downloader settings, including headers per host, or 'none'")
+        ).arg(
+            Arg::with_name("cookies-enable")
+                .long("cookies-enable")
+                .env("SCRAPER_COOKIES_ENABLE")
+                .default_value("false")
+                .value_name("BOOLEAN")
+                .help("Specify whether to keep the cookies set by hosts and send them back for the rest of the crawl")
+        ).arg(
+            Arg::with_name("secrets-file")
+                .long("secrets-file")
+                .env("SCRAPER_SECRETS_FILE")
+                .default_value("none")
+                .value_name("PATH")
+                .help("Specify a YAML file of the credentials of each host, or 'none'")
+        ).arg(
+            Arg::with_name("cache-mode")
+                .long("cache-mode")
+                .env("SCRAPER_CACHE_MODE")
+                .default_value("none")
+                .value_name("STRING")
+                .help("Specify whether to 'record' downloaded pages on disk, 'replay' recorded pages without downloading, 'refresh' the recorded pages, or 'none'")
+        ).arg(
+            Arg::with_name("cache-path")
+                .long("cache-path")
+                .env("SCRAPER_CACHE_PATH")
+                .default_value("cache")
+                .value_name("PATH")
+                .help("Specify the directory of the pages recorded by the cache")
+        ).arg(
+            Arg::with_name("robots-enable")
+                .long("robots-enable")
+                .env("SCRAPER_ROBOTS_ENABLE")
+                .default_value("true")
+                .value_name("BOOLEAN")
+                .help("Specify whether to obey the robots.txt of each host")
+        ).arg(
+            Arg::with_name("robots-ttl")
+                .long("robots-ttl")
+                .env("SCRAPER_ROBOTS_TTL")
+                .default_value("86400")
+                .value_name("SECONDS")
+                .help("Specify how long a downloaded robots.txt is cached")
+        ).arg(
+            Arg::with_name("sitemaps-enable")
+                .long("sitemaps-enable")
+                .env("SCRAPER_SITEMAPS_ENABLE")
+                .default_value("true")
+                .value_name("BOOLEAN")
+                .help("Specify whether to discover sitemaps and extract the urls listed in them")
+        ).arg(
+            Arg::with_name("link-sources")
+                .long("link-sources")
+                .env("SCRAPER_LINK_SOURCES")
+                .default_value(DEFAULT_LINK_SOURCES)
+                .value_name("LIST")
+                .help("Specify a comma-separated list of the elements to find links in, of 'a', 'area', 'link', 'iframe', 'frame', 'meta-refresh', 'srcset', and 'form'")
+        ).arg(
+            Arg::with_name("nofollow-mode")
+                .long("nofollow-mode")
+                .env("SCRAPER_NOFOLLOW_MODE")
+                .default_value("suppress")
+                .value_name("STRING")
+                .help("Specify whether to 'suppress' links marked nofollow, 'deprioritise' them to the lowest priority, or 'ignore' the marking")
+        ).arg(
+            Arg::with_name("noindex-enable")
+                .long("noindex-enable")
+                .env("SCRAPER_NOINDEX_ENABLE")
+                .default_value("true")
+                .value_name("BOOLEAN")
+                .help("Specify whether to skip archiving the data of pages marked noindex")
+        ).arg(
+            Arg::with_name("rules-file")
+                .long("rules-file")
+                .env("SCRAPER_RULES_FILE")
+                .default_value("none")
+                .value_name("PATH")
+                .help("Specify a YAML or JSON file of rules of the data to extract from pages, or 'none'")
+        ).arg(
+            Arg::with_name("structured-data")
+                .long("structured-data")
+                .env("SCRAPER_STRUCTURED_DATA")
+                .default_value("none")
+                .value_name("LIST")
+                .help("Specify a comma-separated list of the formats of structured data to extract from pages, of 'json-ld', 'microdata', 'rdfa', and 'opengraph', or 'none'")
+        ).arg(
+            Arg::with_name("archive-path")
+                .long("archive-path")
+                .env("SCRAPER_ARCHIVE_PATH")
+                .default_value("none")
+                .value_name("PATH")
+                .help("Specify a file to append the extracted data to as JSON lines, or 'none' to discard it")
+        ).arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .env("SCRAPER_MAX_DEPTH")
+                .default_value("none")
+                .value_name("NUMBER")
+                .help("Specify the maximum number of links to follow from a seed, or 'none' for no limit")
+        ).arg(
+            Arg::with_name("recrawl-enable")
+                .long("recrawl-enable")
+                .env("SCRAPER_RECRAWL_ENABLE")
+                .default_value("false")
+                .value_name("BOOLEAN")
+                .help("Specify whether to record when pages are crawled, such that the recrawl subcommand crawls them again. Pages are only downloaded again if they were modified")
+        ).arg(
+            Arg::with_name("recrawl-interval")
+                .long("recrawl-interval")
+                .env("SCRAPER_RECRAWL_INTERVAL")
+                .default_value("86400")
+                .value_name("SECONDS")
+                .help("Specify the interval before a page is recrawled the first time, unless its sitemap hints otherwise")
+        ).arg(
+            Arg::with_name("recrawl-min-interval")
+                .long("recrawl-min-interval")
+                .env("SCRAPER_RECRAWL_MIN_INTERVAL")
+                .default_value("3600")
+                .value_name("SECS")
+                .help("Specify the shortest interval between crawls of a page")
+        ).arg(
+            Arg::with_name("recrawl-max-interval")
+                .long("recrawl-max-interval")
+                .env("SCRAPER_RECRAWL_MAX_INTERVAL")
+                .default_value("2592000")
+                .value_name("SECS")
+                .help("Specify the longest interval between crawls of a page")
+        ).arg(
+            Arg::with_name("max-priority")
+                .long("max-priority")
+                .env("SCRAPER_MAX_PRIORITY")
+                .default_value("0")
+                .value_name("NUMBER")
+                .help("Specify the maximum priority of tasks (at most 255), or 0 to serve tasks in the order they were found")
+        ).arg(
+            Arg::with_name("prioritiser")
+                .long("prioritiser")
+                .env("SCRAPER_PRIORITISER")
+                .default_value("none")
+                .value_name("STRING")
+                .help("Specify how to prioritise tasks: 'depth' (breadth-first), 'opic' (page importance), 'keyword' (relevance to the keywords) or 'none'")
+        ).arg(
+            Arg::with_name("keywords")
+                .long("keywords")
+                .env("SCRAPER_KEYWORDS")
+                .default_value("")
+                .value_name("STRING")
+                .help("Specify a comma-separated list of keywords used by the keyword prioritiser")
         ).arg(
             Arg::with_name("metrics-enable")
                 .short("d")
@@ -266,6 +762,66 @@ fn main() -> Result<(), Box<dyn Error>> {
             .default_value("worker")
             .value_name("STRING")
             .help("Specify the prefix to the naming of the worker")
+        ).subcommand(
+        SubCommand::with_name("dead-letter")
+            .about("Inspect the dead-letter queue or move its entries back to the frontier")
+            .subcommand(
+                SubCommand::with_name("list")
+                    .about("List the entries of the dead-letter queue")
+                    .arg(
+                        Arg::with_name("limit")
+                            .long("limit")
+                            .default_value("100")
+                            .value_name("COUNT")
+                            .help("Specify the maximum number of entries to list")
+                    )
+            ).subcommand(
+                SubCommand::with_name("requeue")
+                    .about("Move entries of the dead-letter queue back to the frontier")
+                    .arg(
+                        Arg::with_name("limit")
+                            .long("limit")
+                            .default_value("100")
+                            .value_name("COUNT")
+                            .help("Specify the maximum number of entries to consider")
+                    ).arg(
+                        Arg::with_name("error-kind")
+                            .long("error-kind")
+                            .value_name("KIND")
+                            .help("Only requeue entries that failed with the given error kind")
+                    ).arg(
+                        Arg::with_name("url-contains")
+                            .long("url-contains")
+                            .value_name("STRING")
+                            .help("Only requeue entries whose task contains the given string")
+                    )
+            )
+        ).subcommand(
+        SubCommand::with_name("drain")
+            .about("Move the tasks of another queue, e.g. the frontier queue of an earlier version, into the partitions of the frontier")
+            .arg(
+                Arg::with_name("queue")
+                    .long("queue")
+                    .required(true)
+                    .value_name("QUEUE")
+                    .help("Specify the queue to move the tasks of")
+            )
+        ).subcommand(
+        SubCommand::with_name("recrawl")
+            .about("Submit pages to the frontier again when they are due to be recrawled")
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .default_value("100")
+                    .value_name("COUNT")
+                    .help("Specify the maximum number of pages to submit at once")
+            ).arg(
+                Arg::with_name("poll-interval")
+                    .long("poll-interval")
+                    .default_value("60")
+                    .value_name("SECONDS")
+                    .help("Specify how long to wait before checking for due pages again")
+            )
         ).get_matches();
 
     // Load config for logging to stdout and logfile.
@@ -302,21 +858,119 @@ fn main() -> Result<(), Box<dyn Error>> {
             None
         };
 
+        let max_priority: u8 = args.value_of("max-priority").unwrap().parse().expect("Failed parsing max priority to u8");
+
         // Construct a worker and its components
-        let manager = RMQRedisManager::new(
-            args.value_of("rmq-address").unwrap().to_string(),
-            args.value_of("rabbitmq-port").unwrap().parse().expect("Failed parsing Rabbitmq port to u16"), // Parse str to u16
-            args.value_of("redis-address").unwrap().to_string(),
-            args.value_of("redis-port").unwrap().parse().expect("Failed parsing Redis port to u16"), // Parse str to u16
-            args.value_of("rabbitmq-exchange").unwrap().to_string(),
-            args.value_of("rabbitmq-prefetch-count").unwrap().parse().expect("Failed parsing prefetch count to u16"), // Parse str to u16
-            args.value_of("rabbitmq-queue").unwrap().to_string(),
-            args.value_of("rabbitmq-collection-queue").unwrap().to_string(),
-            args.value_of("redis-set").unwrap().to_string(),
+        let manager = RMQRedisManager::new(RMQRedisManagerConfig {
+            rmq_addr: args.value_of("rmq-address").unwrap().to_string(),
+            rmq_port: args.value_of("rabbitmq-port").unwrap().parse().expect("Failed parsing Rabbitmq port to u16"), // Parse str to u16
+            redis_addr: args.value_of("redis-address").unwrap().to_string(),
+            redis_port: args.value_of("redis-port").unwrap().parse().expect("Failed parsing Redis port to u16"), // Parse str to u16
+            exchange: args.value_of("rabbitmq-exchange").unwrap().to_string(),
+            prefetch_count: args.value_of("rabbitmq-prefetch-count").unwrap().parse().expect("Failed parsing prefetch count to u16"), // Parse str to u16
+            frontier_queue_name: args.value_of("rabbitmq-queue").unwrap().to_string(),
+            collection_queue_name: args.value_of("rabbitmq-collection-queue").unwrap().to_string(),
+            redis_set: args.value_of("redis-set").unwrap().to_string(),
             sentinel,
-        ).expect("Failed to construct RMQRedisManager");
-        let downloader = DefaultDownloader::new();
-        let extractor = HTMLExtractorBase::new(HTMLLinkExtractor::new());
+            concurrency: args.value_of("concurrency").unwrap().parse().expect("Failed parsing concurrency to usize"), // Parse str to usize
+            retry_policy: retry_policy(&args)?,
+            dead_letter_queue_name: args.value_of("rabbitmq-dead-letter-queue").unwrap().to_string(),
+            worker_name: worker_name.clone(),
+            max_priority,
+            partitions: args.value_of("partitions").unwrap().parse().expect("Failed parsing partitions to u32"),
+        }).expect("Failed to construct RMQRedisManager");
+
+        // Run the dead-letter subcommand instead of the worker if it was given
+        if let ("dead-letter", Some(dead_letter_args)) = args.subcommand() {
+            return run_dead_letter_command(&manager, dead_letter_args);
+        }
+
+        // Run the drain subcommand instead of the worker if it was given
+        if let ("drain", Some(drain_args)) = args.subcommand() {
+            return run_drain_command(&manager, drain_args);
+        }
+
+        let connection_info = format!(
+            "redis://{}:{}/",
+            args.value_of("redis-address").unwrap(),
+            args.value_of("redis-port").unwrap(),
+        ).as_str().into_connection_info()?;
+
+        // Run the recrawl subcommand instead of the worker if it was given
+        if let ("recrawl", Some(recrawl_args)) = args.subcommand() {
+            let scheduler = RedisScheduler::new(create_redis_connection(connection_info, sentinel)?, recrawl_policy(&args));
+            return run_recrawl_command(&manager, &scheduler, recrawl_args);
+        }
+
+        let downloader_config = downloader_config(&args)?;
+        let recrawl_enable: bool = args.value_of("recrawl-enable").unwrap().parse().unwrap();
+        // Validators are only needed when pages are recrawled
+        let default_downloader = if recrawl_enable {
+            DefaultDownloader::with_validators(
+                &downloader_config,
+                RedisValidatorStore::new(create_redis_connection(connection_info.clone(), sentinel)?),
+            )
+        } else {
+            DefaultDownloader::new(&downloader_config)
+        };
+        let proxy_pool = default_downloader.proxy_pool();
+        let downloader: Box<dyn Downloader<Vec<u8>>> = if args.value_of("politeness-enable").unwrap().parse().unwrap() {
+            let limiter = RedisRateLimiter::new(
+                create_redis_connection(connection_info.clone(), sentinel)?,
+                args.value_of("politeness-delay").unwrap().parse().expect("Failed parsing politeness delay to u64"),
+                read_overrides_from_file(args.value_of("politeness-path").unwrap()),
+                args.value_of("politeness-per-ip").unwrap().parse().unwrap(),
+            );
+            let mode = match args.value_of("politeness-mode").unwrap() {
+                "wait" => BusyHostMode::Wait,
+                "requeue" => BusyHostMode::Requeue,
+                other => return Err(format!("Unknown politeness mode {:?}", other).into()),
+            };
+            Box::new(PoliteDownloader::new(Box::new(default_downloader), limiter, mode))
+        } else {
+            Box::new(default_downloader)
+        };
+        // The cache is outermost, such that replayed pages are not delayed by politeness
+        let cache_mode = match args.value_of("cache-mode").unwrap() {
+            "record" => Some(CacheMode::Record),
+            "replay" => Some(CacheMode::Replay),
+            "refresh" => Some(CacheMode::Refresh),
+            "none" => None,
+            other => return Err(format!("Unknown cache mode {:?}", other).into()),
+        };
+        let downloader: Box<dyn Downloader<Vec<u8>>> = match cache_mode {
+            Some(mode) => Box::new(CachingDownloader::new(downloader, args.value_of("cache-path").unwrap(), mode)?),
+            None => downloader,
+        };
+        let directive_policy = DirectivePolicy {
+            nofollow: match args.value_of("nofollow-mode").unwrap() {
+                "deprioritise" => NofollowMode::Deprioritise,
+                "ignore" => NofollowMode::Ignore,
+                "suppress" => NofollowMode::Suppress,
+                other => return Err(format!("Unknown nofollow mode {:?}", other).into()),
+            },
+            noindex: args.value_of("noindex-enable").unwrap().parse().expect("Failed parsing noindex enable to bool"),
+        };
+        // Without a rules file, pages are crawled for their links only
+        let rules = match args.value_of("rules-file").unwrap() {
+            "none" => Rules::default(),
+            path => Rules::from_file(path)?,
+        };
+        let html_extractor = HTMLExtractorBase::with_policy(
+            StructuredDataExtractor::new(
+                parse_formats(args.value_of("structured-data").unwrap()),
+                RulesExtractor::new(
+                    rules,
+                    HTMLLinkExtractor::with_sources(&parse_link_sources(args.value_of("link-sources").unwrap())),
+                ),
+            ),
+            directive_policy,
+        );
+        let extractor: Box<dyn Extractor<Vec<u8>, Record>> = if args.value_of("sitemaps-enable").unwrap().parse().unwrap() {
+            Box::new(SitemapExtractor::new(Box::new(html_extractor), downloader_config.max_body_size))
+        } else {
+            Box::new(html_extractor)
+        };
         let filter: Box<dyn Filter> = if args.value_of("filter-enable").unwrap().parse().unwrap() {
             match args.value_of("filter-type").unwrap() {
                 "black" => Box::new(Blacklist::new(
@@ -329,45 +983,78 @@ fn main() -> Result<(), Box<dyn Error>> {
         } else {
             Box::new(NoFilter)
         };
-        let normaliser = DefaultNormaliser;
-        let archive = Void;
-        let worker = Worker::new(
-            worker_name.as_ref(),
-            Box::new(manager),
-            Box::new(downloader),
-            Box::new(extractor),
-            Box::new(normaliser),
-            Box::new(archive),
-            filter,
-        );
-
-        let influx_credentials = if args.value_of("influx-authenticate").unwrap().parse().unwrap() {
-            Some(InfluxCredentials {
-                username: args.value_of("influx-username").unwrap().to_string(),
-                password: args.value_of("influx-password").unwrap().to_string(),
-            })
+        let robots: Box<dyn Filter> = if args.value_of("robots-enable").unwrap().parse().unwrap() {
+            // robots.txt is fetched through the same proxies as the pages
+            Box::new(RobotsFilter::with_proxies(
+                create_redis_connection(connection_info.clone(), sentinel)?,
+                &downloader_config.user_agent,
+                args.value_of("robots-ttl").unwrap().parse().expect("Failed parsing robots ttl to u64"),
+                proxy_pool.clone(),
+            ))
         } else {
-            None
+            Box::new(NoFilter)
         };
-
-        let influxdb_client = if args
-            .value_of("metrics-enable")
-            .unwrap()
-            .parse()
-            .expect("The 'metrics-enable' argument was not a boolean")
-        {
-            Some(InfluxClient::new(
-                args.value_of("influx-addr").unwrap(),
-                args.value_of("influx-port")
-                    .unwrap()
-                    .parse()
-                    .expect("The 'influx-port' argument was not an int"),
-                influx_credentials,
-                args.value_of("influx-database").unwrap(),
+        let max_depth = match args.value_of("max-depth").unwrap() {
+            "none" => None,
+            depth => Some(depth.parse().expect("Failed parsing max depth to u32")),
+        };
+        let prioritiser_arg = args.value_of("prioritiser").unwrap();
+        if prioritiser_arg != "none" && max_priority == 0 {
+            return Err(format!("The {} prioritiser needs a maximum priority above 0", prioritiser_arg).into());
+        }
+        let prioritiser: Box<dyn Prioritiser> = match prioritiser_arg {
+            "depth" => Box::new(DepthPrioritiser::new(max_priority)),
+            "opic" => Box::new(OPICPrioritiser::new(
+                create_redis_connection(connection_info.clone(), sentinel)?,
+                max_priority,
+            )),
+            "keyword" => Box::new(KeywordPrioritiser::new(
+                args.value_of("keywords").unwrap().split(',').map(String::from).collect(),
+                max_priority,
+            )),
+            "none" => Box::new(NoPrioritiser),
+            other => return Err(format!("Unknown prioritiser {:?}", other).into()),
+        };
+        let scheduler: Box<dyn Scheduler> = if recrawl_enable {
+            Box::new(RedisScheduler::new(
+                create_redis_connection(connection_info, sentinel)?,
+                recrawl_policy(&args),
             ))
         } else {
-            None
+            Box::new(NoScheduler)
+        };
+        let normaliser = DefaultNormaliser;
+        let archive: Box<dyn Archive<Record>> = match args.value_of("archive-path").unwrap() {
+            "none" => Box::new(Void),
+            path => Box::new(FileArchive::new(path)?),
         };
+        let worker = Worker::new(
+            worker_name.as_ref(),
+            WorkerComponents {
+                manager: Box::new(manager),
+                downloader,
+                extractor,
+                normaliser: Box::new(normaliser),
+                prioritiser,
+                archive,
+                filter,
+                robots,
+                scheduler,
+            },
+            max_depth,
+        );
+
+        // Report the health of the proxies alongside the metrics of the worker
+        let influxdb_client = influx_client(&args).map(Arc::new);
+        if let (Some(pool), Some(client)) = (proxy_pool, influxdb_client.clone()) {
+            let worker_name = worker_name.clone();
+            thread::spawn(move || loop {
+                for (proxy, health) in pool.health() {
+                    write_proxy_health(&proxy, &health, &worker_name, &client);
+                }
+                thread::sleep(PROXY_HEALTH_INTERVAL);
+            });
+        }
 
         worker.start(influxdb_client);
 
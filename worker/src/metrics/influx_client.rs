@@ -1,5 +1,9 @@
 use influx_db_client::{Client, Point, Points, Value, Precision, error};
 use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::credentials::redact_url;
+use crate::proxypool::ProxyHealth;
 
 pub struct InfluxCredentials {
     pub username: String,
@@ -85,6 +89,11 @@ impl TimeSession {
         self.last_time = time;
     }
 
+    /// Adds a tag to Point, e.g. to describe the task being measured
+    pub fn add_tag(&mut self, tag: &str, value: &str) {
+        self.point.add_tag(tag, Value::String(value.to_string()));
+    }
+
     /// Add task finishing time to field 'task_finishing_time'
     pub fn add_finishing_time(&mut self) {
         self.point.add_field("task_finishing_time", Value::Integer(get_timestamp_millis() - self.start_time));
@@ -114,6 +123,11 @@ impl CountSession {
         };
     }
 
+    /// Adds a tag to Point, e.g. to describe the task being measured
+    pub fn add_tag(&mut self, tag: &str, value: &str) {
+        self.point.add_tag(tag, Value::String(value.to_string()));
+    }
+
     pub fn add_first_count_field(&mut self, field: &str, count: i64) {
         self.point.add_field(field, Value::Integer(count));
         self.last_count = count;
@@ -134,24 +148,44 @@ impl CountSession {
     }
 }
 
-/// Write a task_url measurement with a given name, tagged by worker instance to Influx for
-/// duplicate work statistics
-pub fn write_task_url(url: &str, measurement: &str, worker_instance: &str, client: &InfluxClient) {
+/// Write a task_url measurement with a given name, tagged by worker instance and task depth to
+/// Influx for duplicate work statistics
+pub fn write_task_url(url: &Url, depth: u32, measurement: &str, worker_instance: &str, client: &InfluxClient) {
     client.write_point(Point::new(measurement)
         .add_timestamp(get_timestamp_millis())
         .add_tag("instance", Value::String(worker_instance.to_string()))
-        .add_field("task_url", Value::String(url.to_string()))
+        .add_tag("depth", Value::String(depth.to_string()))
+        .add_field("task_url", Value::String(redact_url(url).to_string()))
         .to_owned());
 }
 
-/// Write a task_url measurement with a given name, error-kind, tagged by worker instance
-/// to Influx for error statistics
-pub fn write_task_error_url(url: &str, measurement: &str, error_kind: &str, worker_instance: &str, client: &InfluxClient) {
-    client.write_point(Point::new(measurement)
+/// Write a task_url measurement with a given name, error-kind, tagged by worker instance, task
+/// depth, and the HTTP status code of failed downloads to Influx for error statistics
+pub fn write_task_error_url(url: &Url, depth: u32, measurement: &str, error_kind: &str, status: Option<u16>, worker_instance: &str, client: &InfluxClient) {
+    let mut point = Point::new(measurement)
         .add_timestamp(get_timestamp_millis())
         .add_tag("instance", Value::String(worker_instance.to_string()))
-        .add_field("task_url", Value::String(url.to_string()))
+        .add_tag("depth", Value::String(depth.to_string()))
+        .add_field("task_url", Value::String(redact_url(url).to_string()))
         .add_tag("error_kind", Value::String(error_kind.to_string()))
+        .to_owned();
+    if let Some(status) = status {
+        point.add_tag("status", Value::String(status.to_string()));
+    }
+    client.write_point(point);
+}
+
+/// Write a proxy_health measurement, tagged by worker instance and proxy, with the number of
+/// requests and failures of the proxy and whether it is in rotation
+pub fn write_proxy_health(proxy: &str, health: &ProxyHealth, worker_instance: &str, client: &InfluxClient) {
+    client.write_point(Point::new("proxy_health")
+        .add_timestamp(get_timestamp_millis())
+        .add_tag("instance", Value::String(worker_instance.to_string()))
+        .add_tag("proxy", Value::String(proxy.to_string()))
+        .add_field("requests", Value::Integer(health.requests as i64))
+        .add_field("failures", Value::Integer(health.failures as i64))
+        .add_field("consecutive_failures", Value::Integer(i64::from(health.consecutive_failures)))
+        .add_field("available", Value::Boolean(health.available))
         .to_owned());
 }
 
@@ -0,0 +1,132 @@
+use futures::{Async, Poll, Stream};
+use url::Url;
+
+/// The routing key that every partition of the frontier is bound with, i.e. the binding of
+/// queues that receive every task such as the collection queue
+pub(crate) const ALL_PARTITIONS_ROUTING_KEY: &str = "#";
+
+/// The partition of the frontier that tasks of the given Url belong to. Every task of a host
+/// belongs to the same partition, such that a host with many links only fills its own partition.
+///
+/// The FNV-1a hash of the host is used, as it is stable across builds and platforms, which
+/// is required for workers to agree on the partitions.
+pub(crate) fn partition_of(url: &Url, partitions: u32) -> u32 {
+    if partitions <= 1 {
+        return 0;
+    }
+
+    let host = url.host_str().unwrap_or("");
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in host.bytes() {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash % partitions
+}
+
+/// The routing key of the tasks of the given partition
+pub(crate) fn partition_routing_key(partition: u32) -> String {
+    partition.to_string()
+}
+
+/// Name of the queue of the given partition of the frontier. A frontier with a single partition
+/// keeps the name of the frontier queue.
+pub(crate) fn partition_queue_name(frontier_queue_name: &str, partition: u32, partitions: u32) -> String {
+    if partitions <= 1 {
+        String::from(frontier_queue_name)
+    } else {
+        format!("{}.{}", frontier_queue_name, partition)
+    }
+}
+
+/// The RoundRobin is a Stream that takes turns receiving items from each of a number of
+/// streams, such that a stream with many items ready cannot starve the others. Streams that
+/// have ended are skipped, and the RoundRobin ends when every stream has ended.
+pub(crate) struct RoundRobin<S> {
+    streams: Vec<S>,
+    next: usize,
+}
+
+impl<S: Stream> RoundRobin<S> {
+    /// Construct a new RoundRobin over the given streams
+    pub fn new(streams: Vec<S>) -> Self {
+        RoundRobin { streams, next: 0 }
+    }
+}
+
+impl<S: Stream> Stream for RoundRobin<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // Poll every stream once, starting with the stream after the one that was served last
+        let mut polled = 0;
+        while polled < self.streams.len() {
+            let index = self.next % self.streams.len();
+            match self.streams[index].poll()? {
+                Async::Ready(Some(item)) => {
+                    self.next = index + 1;
+                    return Ok(Async::Ready(Some(item)));
+                }
+                Async::Ready(None) => {
+                    // The stream has ended, so stop polling it
+                    self.streams.remove(index);
+                    self.next = index;
+                }
+                Async::NotReady => {
+                    self.next = index + 1;
+                    polled += 1;
+                }
+            }
+        }
+
+        if self.streams.is_empty() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+    use futures::stream;
+    use url::Url;
+
+    use crate::partition::{partition_of, partition_queue_name, RoundRobin};
+
+    /// Every Url of a host belongs to the same partition
+    #[test]
+    fn partition_of_host() {
+        let a = partition_of(&Url::parse("http://aau.dk/a").unwrap(), 16);
+        let b = partition_of(&Url::parse("https://aau.dk/b?c=d").unwrap(), 16);
+        assert_eq!(a, b);
+        assert!(a < 16);
+    }
+
+    /// The partitions must not change between builds, as workers must agree on them
+    #[test]
+    fn partition_of_is_stable() {
+        assert_eq!(partition_of(&Url::parse("http://aau.dk/").unwrap(), 1000), 2_844_432_973 % 1000);
+        assert_eq!(partition_of(&Url::parse("http://aau.dk/").unwrap(), 1), 0);
+    }
+
+    #[test]
+    fn partition_queue_names() {
+        assert_eq!(partition_queue_name("frontier", 0, 1), "frontier");
+        assert_eq!(partition_queue_name("frontier", 3, 8), "frontier.3");
+    }
+
+    /// Streams take turns, and ended streams are skipped
+    #[test]
+    fn round_robin_takes_turns() {
+        let streams = vec![
+            stream::iter_ok::<_, ()>(vec![1, 2, 3, 4]),
+            stream::iter_ok(vec![10]),
+            stream::iter_ok(vec![20, 21]),
+        ];
+        let items = RoundRobin::new(streams).collect().wait().unwrap();
+        assert_eq!(items, vec![1, 10, 20, 2, 21, 3, 4]);
+    }
+}
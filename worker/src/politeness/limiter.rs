@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::ops::DerefMut;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use redis::{Connection, RedisResult};
+use url::Url;
+
+use crate::credentials::redact_url;
+use crate::errors::{DownloadError, DownloadResult};
+use crate::errors::DownloadErrorKind::HostBusy;
+use crate::robots::filter::crawl_delay_key;
+use crate::task::Task;
+use crate::traits::{Downloader, Response};
+
+/// Prefix of the Redis keys that mark a host as recently visited
+const VISIT_KEY_PREFIX: &str = "politeness";
+
+/// Describes what to do with a task when its host has been visited too recently
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BusyHostMode {
+    /// Wait until the host may be visited again
+    Wait,
+    /// Give the task back to the manager, such that it is resolved later
+    Requeue,
+}
+
+/// The RedisRateLimiter keeps track of when each host was last visited by any worker in the
+/// cluster, such that a host is visited at most once per delay. The delay of a host is the
+/// default delay, or the Crawl-delay of its robots.txt if that is longer, unless it has been
+/// overridden.
+pub struct RedisRateLimiter {
+    connection: Mutex<Connection>,
+    default_delay_ms: u64,
+    overrides: HashMap<String, u64>,
+    per_ip: bool,
+}
+
+impl RedisRateLimiter {
+    /// Construct a new RedisRateLimiter. If `per_ip` is true, hosts that resolve to the same IP
+    /// address share their budget.
+    pub fn new(connection: Connection, default_delay_ms: u64, overrides: HashMap<String, u64>, per_ip: bool) -> Self {
+        RedisRateLimiter {
+            connection: Mutex::new(connection),
+            default_delay_ms,
+            overrides,
+            per_ip,
+        }
+    }
+
+    /// The delay in milliseconds between visits to the given host
+    fn delay_ms(&self, con: &mut Connection, host: &str) -> RedisResult<u64> {
+        if let Some(delay) = find_override(&self.overrides, host) {
+            return Ok(delay);
+        }
+
+        // The Crawl-delay is stored by the robots.txt handling, if the host has one
+        let crawl_delay: Option<u64> = redis::cmd("GET").arg(crawl_delay_key(host)).query(con)?;
+        Ok(match crawl_delay {
+            Some(crawl_delay) => crawl_delay.max(self.default_delay_ms),
+            None => self.default_delay_ms,
+        })
+    }
+
+    /// The key that identifies the budget of the host of the given url, if it has a host. The key
+    /// is based on the resolved IP address of the host when limiting per IP, and on the host name
+    /// otherwise. Resolving the host may take long, so the key must be found before the Redis
+    /// connection is locked.
+    pub fn budget_key(&self, url: &Url) -> Option<String> {
+        let host = url.host_str()?;
+        if self.per_ip {
+            let ip = url.socket_addrs(|| None)
+                .ok()
+                .and_then(|addrs| addrs.first().map(|addr| addr.ip().to_string()));
+            if let Some(ip) = ip {
+                return Some(format!("{}:ip:{}", VISIT_KEY_PREFIX, ip));
+            }
+            // Fall back to the host name if it cannot be resolved
+        }
+        Some(format!("{}:host:{}", VISIT_KEY_PREFIX, host))
+    }
+
+    /// Try to reserve a visit to the host of the given url, whose budget has the given key.
+    /// Returns None if the host may be visited now, otherwise the number of milliseconds until it
+    /// may be visited.
+    pub fn reserve(&self, url: &Url, key: &str) -> RedisResult<Option<u64>> {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return Ok(None),
+        };
+
+        let mut con = self.connection.lock().expect("Redis connection mutex was corrupted");
+        let delay_ms = self.delay_ms(con.deref_mut(), host)?;
+        if delay_ms == 0 {
+            return Ok(None);
+        }
+
+        // The key only exists while the host was visited within the last delay. If it could be
+        // set, no other worker has visited the host within the delay
+        let reserved: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(delay_ms)
+            .query(con.deref_mut())?;
+        if reserved.is_some() {
+            return Ok(None);
+        }
+
+        // The remaining time to live of the key is the time until the host may be visited
+        let remaining_ms: i64 = redis::cmd("PTTL").arg(key).query(con.deref_mut())?;
+        Ok(Some(remaining_ms.max(1) as u64))
+    }
+}
+
+/// The PoliteDownloader is a Downloader that makes sure hosts are not visited more often than
+/// the RedisRateLimiter allows before downloading pages with another Downloader.
+pub struct PoliteDownloader<S> {
+    downloader: Box<dyn Downloader<S>>,
+    limiter: RedisRateLimiter,
+    mode: BusyHostMode,
+}
+
+impl<S> PoliteDownloader<S> {
+    /// Construct a new PoliteDownloader that downloads pages with the given Downloader
+    pub fn new(downloader: Box<dyn Downloader<S>>, limiter: RedisRateLimiter, mode: BusyHostMode) -> Self {
+        PoliteDownloader {
+            downloader,
+            limiter,
+            mode,
+        }
+    }
+}
+
+impl<S> Downloader<S> for PoliteDownloader<S> {
+    fn fetch_page(&self, task: &Task) -> DownloadResult<Response<S>> {
+        // Urls without hosts are not limited
+        let key = match self.limiter.budget_key(&task.url) {
+            Some(key) => key,
+            None => return self.downloader.fetch_page(task),
+        };
+
+        loop {
+            match self.limiter.reserve(&task.url, &key) {
+                Ok(None) => break,
+                Ok(Some(wait_ms)) => match self.mode {
+                    // Another worker may reserve the host in the meantime, so try again after waiting
+                    BusyHostMode::Wait => thread::sleep(Duration::from_millis(wait_ms)),
+                    BusyHostMode::Requeue => {
+                        return Err(DownloadError::new(HostBusy(wait_ms), "Host was visited too recently", None));
+                    }
+                },
+                Err(e) => {
+                    // Rather download the page than not resolving tasks at all
+                    warn!("Failed to check politeness of {}. {}", redact_url(&task.url), e);
+                    break;
+                }
+            }
+        }
+
+        self.downloader.fetch_page(task)
+    }
+}
+
+/// Finds the delay override of the given host. Overrides apply to the host itself and all of its
+/// subdomains, and the most specific override is used.
+fn find_override(overrides: &HashMap<String, u64>, host: &str) -> Option<u64> {
+    let mut domain = host;
+    loop {
+        if let Some(delay) = overrides.get(domain) {
+            return Some(*delay);
+        }
+        // Continue with the parent domain, if any
+        match domain.find('.') {
+            Some(index) => domain = &domain[index + 1..],
+            None => return None,
+        }
+    }
+}
+
+/// Reads per-host delay overrides from a file. Each line consists of a host and a delay in
+/// milliseconds separated by whitespace. Empty lines and lines starting with '#' are ignored.
+/// A missing file has no overrides.
+pub fn read_overrides_from_file(path: &str) -> HashMap<String, u64> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("No politeness overrides file at {:?}", path);
+            return HashMap::new();
+        }
+        Err(e) => panic!("Could not open file: {:?}. {}", path, e),
+    };
+    let lines: Vec<String> = BufReader::new(file)
+        .lines()
+        .map(|l| l.unwrap())
+        .collect();
+
+    parse_overrides(&lines)
+}
+
+/// Parses per-host delay overrides. Malformed lines are logged and skipped.
+fn parse_overrides(lines: &[String]) -> HashMap<String, u64> {
+    let mut overrides = HashMap::new();
+    for line in lines.iter().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next().map(|delay| delay.parse::<u64>())) {
+            (Some(host), Some(Ok(delay))) => {
+                overrides.insert(host.to_lowercase(), delay);
+            }
+            _ => error!("Skipping malformed politeness override: {:?}", line),
+        }
+    }
+
+    overrides
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::politeness::limiter::{find_override, parse_overrides, read_overrides_from_file};
+
+    /// Test that overrides are parsed, while comments and malformed lines are skipped
+    #[test]
+    fn parse_overrides_test() {
+        let lines: Vec<String> = ["# Comment", "", "dr.dk 5000", "  AAU.dk\t2000  ", "tv2.dk", "bbc.co.uk soon"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+
+        let mut expected = HashMap::new();
+        expected.insert(String::from("dr.dk"), 5000);
+        expected.insert(String::from("aau.dk"), 2000);
+        assert_eq!(parse_overrides(&lines), expected);
+    }
+
+    #[test]
+    fn read_missing_overrides_file() {
+        assert!(read_overrides_from_file("no/such/overrides.txt").is_empty());
+    }
+
+    /// Test that overrides apply to the host and its subdomains, preferring the most specific
+    #[test]
+    fn find_override_test() {
+        let mut overrides = HashMap::new();
+        overrides.insert(String::from("dr.dk"), 5000);
+        overrides.insert(String::from("nyheder.dr.dk"), 1000);
+
+        assert_eq!(find_override(&overrides, "dr.dk"), Some(5000));
+        assert_eq!(find_override(&overrides, "www.dr.dk"), Some(5000));
+        assert_eq!(find_override(&overrides, "nyheder.dr.dk"), Some(1000));
+        assert_eq!(find_override(&overrides, "tv2.dk"), None);
+        assert_eq!(find_override(&overrides, "notdr.dk"), None);
+    }
+}
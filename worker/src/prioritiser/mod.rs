@@ -0,0 +1,2 @@
+pub(crate) mod opic;
+pub(crate) mod simple;
@@ -0,0 +1,107 @@
+use std::ops::DerefMut;
+use std::sync::Mutex;
+
+use redis::{Connection, RedisResult};
+
+use crate::credentials::redact_url;
+use crate::task::Task;
+use crate::traits::{Link, Prioritiser};
+
+/// The Redis hash that holds the cash of each page
+const CASH_KEY: &str = "opic:cash";
+/// The cash of a page that has not received any cash yet, such as a seed
+const INITIAL_CASH: f64 = 1.0;
+
+/// The OPICPrioritiser is a Prioritiser implementing the On-line Page Importance Computation.
+/// Every page holds an amount of cash. When a page is resolved, its cash is distributed evenly
+/// among the pages it links to, and the links are prioritised by the cash of the page they
+/// lead to. Pages linked from many important pages thereby get higher priorities.
+///
+/// The cash is kept in Redis, such that all workers in the cluster share it. As the frontier
+/// cannot change the priority of a task that has already been submitted, the priority of a
+/// link reflects the cash of its page at the time it was first found.
+pub(crate) struct OPICPrioritiser {
+    connection: Mutex<Connection>,
+    max_priority: u8,
+}
+
+impl OPICPrioritiser {
+    /// Construct a new OPICPrioritiser giving priorities from 0 to `max_priority`
+    pub fn new(connection: Connection, max_priority: u8) -> Self {
+        OPICPrioritiser {
+            connection: Mutex::new(connection),
+            max_priority,
+        }
+    }
+
+    /// Take the cash of the parent and distribute it among the given links. Returns the cash
+    /// of each link after the distribution.
+    fn distribute_cash(&self, parent: &Task, links: &[Link]) -> RedisResult<Vec<f64>> {
+        let mut con = self.connection.lock().expect("Redis connection mutex was corrupted");
+
+        // Take the cash of the parent, such that it is distributed only once
+        let (cash,): (Option<f64>,) = redis::pipe()
+            .atomic()
+            .cmd("HGET").arg(CASH_KEY).arg(parent.url.as_str())
+            .cmd("HSET").arg(CASH_KEY).arg(parent.url.as_str()).arg(0).ignore()
+            .query(con.deref_mut())?;
+        let share = cash.unwrap_or(INITIAL_CASH) / links.len().max(1) as f64;
+
+        let mut pipeline = redis::pipe();
+        for link in links.iter() {
+            pipeline.cmd("HINCRBYFLOAT").arg(CASH_KEY).arg(link.url.as_str()).arg(share);
+        }
+        pipeline.query(con.deref_mut())
+    }
+}
+
+impl Prioritiser for OPICPrioritiser {
+    fn prioritise(&self, parent: &Task, mut links: Vec<Link>) -> Vec<Link> {
+        if links.is_empty() {
+            return links;
+        }
+
+        match self.distribute_cash(parent, &links) {
+            Ok(cash) => {
+                for (link, cash) in links.iter_mut().zip(cash) {
+                    link.priority = link.priority.or(Some(cash_to_priority(cash, self.max_priority)));
+                }
+            }
+            // The links are left without a priority rather than being lost
+            Err(e) => warn!("Failed to distribute the cash of {}. {}", redact_url(&parent.url), e),
+        }
+        links
+    }
+}
+
+/// Convert an amount of cash to a priority. Cash is spread thin quickly, so the priority is
+/// logarithmic in the cash, where the initial cash of a seed gives the maximum priority and each
+/// halving of the cash lowers the priority by one.
+fn cash_to_priority(cash: f64, max_priority: u8) -> u8 {
+    if cash <= 0.0 {
+        return 0;
+    }
+    let priority = f64::from(max_priority) + (cash / INITIAL_CASH).log2();
+    priority.round().max(0.0).min(f64::from(max_priority)) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prioritiser::opic::cash_to_priority;
+
+    #[test]
+    fn cash_to_priority_halving() {
+        assert_eq!(cash_to_priority(1.0, 10), 10);
+        assert_eq!(cash_to_priority(0.5, 10), 9);
+        assert_eq!(cash_to_priority(0.25, 10), 8);
+    }
+
+    /// Priorities stay within 0 and the maximum priority
+    #[test]
+    fn cash_to_priority_bounds() {
+        assert_eq!(cash_to_priority(4.0, 10), 10);
+        assert_eq!(cash_to_priority(0.0001, 10), 0);
+        assert_eq!(cash_to_priority(0.0, 10), 0);
+        assert_eq!(cash_to_priority(-1.0, 10), 0);
+    }
+}
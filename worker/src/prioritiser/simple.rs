@@ -0,0 +1,146 @@
+use crate::task::Task;
+use crate::traits::{Link, Prioritiser};
+
+/// Functions as a prioritiser but is doing nothing, thereby leaving links without a priority
+pub(crate) struct NoPrioritiser;
+
+impl Prioritiser for NoPrioritiser {
+    fn prioritise(&self, _parent: &Task, links: Vec<Link>) -> Vec<Link> { links }
+}
+
+/// The DepthPrioritiser is a Prioritiser that prioritises links by the depth at which they are
+/// found, such that the frontier is crawled breadth-first. Links found on seeds get the maximum
+/// priority, and every link followed after that lowers the priority by one.
+pub(crate) struct DepthPrioritiser {
+    max_priority: u8,
+}
+
+impl DepthPrioritiser {
+    /// Construct a new DepthPrioritiser giving priorities from 0 to `max_priority`
+    pub fn new(max_priority: u8) -> Self {
+        DepthPrioritiser { max_priority }
+    }
+}
+
+impl Prioritiser for DepthPrioritiser {
+    fn prioritise(&self, parent: &Task, mut links: Vec<Link>) -> Vec<Link> {
+        // Links found on seeds, i.e. at depth 1, get the maximum priority
+        let priority = u32::from(self.max_priority).saturating_sub(parent.depth) as u8;
+        for link in links.iter_mut() {
+            link.priority = link.priority.or(Some(priority));
+        }
+        links
+    }
+}
+
+/// The KeywordPrioritiser is a Prioritiser that prioritises links by their relevance to a set
+/// of keywords. The relevance of a link is the fraction of the keywords found in either its Url
+/// or its anchor text.
+pub(crate) struct KeywordPrioritiser {
+    keywords: Vec<String>,
+    max_priority: u8,
+}
+
+impl KeywordPrioritiser {
+    /// Construct a new KeywordPrioritiser giving priorities from 0 to `max_priority`. Keywords
+    /// are matched regardless of case.
+    pub fn new(keywords: Vec<String>, max_priority: u8) -> Self {
+        KeywordPrioritiser {
+            keywords: keywords.iter()
+                .map(|keyword| keyword.trim().to_lowercase())
+                .filter(|keyword| !keyword.is_empty())
+                .collect(),
+            max_priority,
+        }
+    }
+
+    /// The fraction of the keywords found in the Url or anchor text of the link
+    fn relevance(&self, link: &Link) -> f64 {
+        if self.keywords.is_empty() {
+            return 0.0;
+        }
+
+        let url = link.url.as_str().to_lowercase();
+        let text = link.text.as_ref().map(|text| text.to_lowercase()).unwrap_or_default();
+        let found = self.keywords.iter()
+            .filter(|keyword| url.contains(keyword.as_str()) || text.contains(keyword.as_str()))
+            .count();
+
+        found as f64 / self.keywords.len() as f64
+    }
+}
+
+impl Prioritiser for KeywordPrioritiser {
+    fn prioritise(&self, _parent: &Task, mut links: Vec<Link>) -> Vec<Link> {
+        for link in links.iter_mut() {
+            if link.priority.is_none() {
+                let priority = (self.relevance(link) * f64::from(self.max_priority)).round();
+                link.priority = Some(priority as u8);
+            }
+        }
+        links
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::prioritiser::simple::{DepthPrioritiser, KeywordPrioritiser};
+    use crate::task::Task;
+    use crate::traits::{Link, Prioritiser};
+
+    fn link(url: &str, text: Option<&str>) -> Link {
+        let mut link = Link::new(Url::parse(url).unwrap());
+        link.text = text.map(String::from);
+        link
+    }
+
+    fn priorities(links: &[Link]) -> Vec<Option<u8>> {
+        links.iter().map(|link| link.priority).collect()
+    }
+
+    /// Deeper links get lower priorities, down to zero
+    #[test]
+    fn depth_prioritiser() {
+        let prioritiser = DepthPrioritiser::new(3);
+        let mut parent = Task::new(Url::parse("http://aau.dk/").unwrap());
+
+        let links = prioritiser.prioritise(&parent, vec![link("http://aau.dk/a", None)]);
+        assert_eq!(priorities(&links), vec![Some(3)]);
+
+        parent.depth = 2;
+        let links = prioritiser.prioritise(&parent, vec![link("http://aau.dk/a", None)]);
+        assert_eq!(priorities(&links), vec![Some(1)]);
+
+        parent.depth = 10;
+        let links = prioritiser.prioritise(&parent, vec![link("http://aau.dk/a", None)]);
+        assert_eq!(priorities(&links), vec![Some(0)]);
+    }
+
+    /// Priorities decided by the extractor are kept
+    #[test]
+    fn depth_prioritiser_keeps_priority() {
+        let prioritiser = DepthPrioritiser::new(3);
+        let parent = Task::new(Url::parse("http://aau.dk/").unwrap());
+        let mut decided = link("http://aau.dk/a", None);
+        decided.priority = Some(1);
+
+        let links = prioritiser.prioritise(&parent, vec![decided]);
+        assert_eq!(priorities(&links), vec![Some(1)]);
+    }
+
+    /// Keywords are found in either the Url or the anchor text, regardless of case
+    #[test]
+    fn keyword_prioritiser() {
+        let prioritiser = KeywordPrioritiser::new(vec![String::from("Research"), String::from("robot")], 10);
+        let parent = Task::new(Url::parse("http://aau.dk/").unwrap());
+
+        let links = prioritiser.prioritise(&parent, vec![
+            link("http://aau.dk/research/robots", None),
+            link("http://aau.dk/news/1", Some("New research in AI")),
+            link("http://aau.dk/contact", Some("Contact us")),
+        ]);
+        assert_eq!(priorities(&links), vec![Some(10), Some(5), Some(0)]);
+    }
+}
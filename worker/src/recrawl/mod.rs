@@ -0,0 +1,2 @@
+pub(crate) mod scheduler;
+pub(crate) mod validators;
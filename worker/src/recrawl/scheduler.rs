@@ -0,0 +1,264 @@
+use std::hash::{Hash, Hasher};
+use std::ops::DerefMut;
+use std::sync::Mutex;
+
+use redis::{Connection, RedisResult};
+
+use crate::credentials::redact_str;
+use crate::errors::{ManagerError, ManagerResult};
+use crate::errors::ManagerErrorKind::UnreachableError;
+use crate::metrics::influx_client::get_timestamp_millis;
+use crate::recrawl::validators::Validators;
+use crate::task::{ChangeFrequency, Task};
+use crate::traits::Scheduler;
+
+/// Prefix of the Redis hashes that hold the crawl history of each url
+const CRAWL_KEY_PREFIX: &str = "crawl";
+/// The Redis sorted set of urls to recrawl, scored by the time they are due in milliseconds
+const RECRAWL_KEY: &str = "recrawl";
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+const DAY_MS: u64 = 24 * HOUR_MS;
+
+/// The key of the crawl history of the given url
+pub(crate) fn crawl_key(url: &str) -> String {
+    format!("{}:{}", CRAWL_KEY_PREFIX, url)
+}
+
+/// Functions as a scheduler but is doing nothing, thereby never recrawling any page
+pub(crate) struct NoScheduler;
+
+impl Scheduler for NoScheduler {
+    fn record_crawl(&self, _task: &Task, _content_hash: Option<u64>, _validators: Option<&Validators>) -> ManagerResult<()> { Ok(()) }
+}
+
+/// The RecrawlPolicy describes how often pages are recrawled. The interval of a page starts at
+/// the interval hinted by its sitemap, or the default interval, and is then halved whenever its
+/// content has changed since the last crawl and doubled whenever it has not.
+#[derive(Debug, Clone)]
+pub struct RecrawlPolicy {
+    pub default_interval_ms: u64,
+    pub min_interval_ms: u64,
+    pub max_interval_ms: u64,
+}
+
+impl RecrawlPolicy {
+    /// Limit an interval to the minimum and maximum interval
+    fn clamp(&self, interval_ms: u64) -> u64 {
+        interval_ms.max(self.min_interval_ms).min(self.max_interval_ms)
+    }
+
+    /// The interval before a page is crawled the first time it is recrawled
+    fn initial_interval_ms(&self, changefreq: Option<ChangeFrequency>) -> u64 {
+        let interval_ms = match changefreq {
+            Some(ChangeFrequency::Always) => self.min_interval_ms,
+            Some(ChangeFrequency::Hourly) => HOUR_MS,
+            Some(ChangeFrequency::Daily) => DAY_MS,
+            Some(ChangeFrequency::Weekly) => 7 * DAY_MS,
+            Some(ChangeFrequency::Monthly) => 30 * DAY_MS,
+            Some(ChangeFrequency::Yearly) => 365 * DAY_MS,
+            Some(ChangeFrequency::Never) => self.max_interval_ms,
+            None => self.default_interval_ms,
+        };
+        self.clamp(interval_ms)
+    }
+
+    /// The interval before a page is crawled again. `previous` is the content hash and interval
+    /// of the previous crawl of the page, if it has been crawled before.
+    pub fn next_interval_ms(&self, previous: Option<(u64, u64)>, content_hash: u64, changefreq: Option<ChangeFrequency>) -> u64 {
+        match previous {
+            None => self.initial_interval_ms(changefreq),
+            // The content changed within the interval, so check more often
+            Some((previous_hash, interval_ms)) if previous_hash != content_hash => self.clamp(interval_ms / 2),
+            // The content did not change within the interval, so check less often
+            Some((_, interval_ms)) => self.clamp(interval_ms.saturating_mul(2)),
+        }
+    }
+}
+
+/// The RedisScheduler is a Scheduler that stores the crawl history of every page in Redis. The
+/// history of a page consists of its task, the time it was last crawled, the hash of its content,
+/// and its recrawl interval. Pages are added to a sorted set by the time they are due to be
+/// recrawled, from where `take_due` takes them once they are due.
+pub struct RedisScheduler {
+    connection: Mutex<Connection>,
+    policy: RecrawlPolicy,
+}
+
+impl RedisScheduler {
+    /// Construct a new RedisScheduler that recrawls pages as described by the given policy
+    pub fn new(connection: Connection, policy: RecrawlPolicy) -> Self {
+        RedisScheduler {
+            connection: Mutex::new(connection),
+            policy,
+        }
+    }
+
+    /// Take up to `limit` tasks that are due to be recrawled. A task is only taken once, even
+    /// if several schedulers take tasks at the same time. Taken tasks that could not be submitted
+    /// must be given back with `restore`.
+    pub fn take_due(&self, limit: usize) -> RedisResult<Vec<Task>> {
+        let mut con = self.connection.lock().expect("Redis connection mutex was corrupted");
+
+        let urls: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(RECRAWL_KEY)
+            .arg("-inf")
+            .arg(get_timestamp_millis())
+            .arg("LIMIT")
+            .arg(0)
+            .arg(limit)
+            .query(con.deref_mut())?;
+
+        let mut tasks = vec![];
+        for url in urls.iter() {
+            // Another scheduler took the task first if it has already been removed
+            let removed: u32 = redis::cmd("ZREM").arg(RECRAWL_KEY).arg(url.as_str()).query(con.deref_mut())?;
+            if removed == 0 {
+                continue;
+            }
+
+            let data: Option<Vec<u8>> = redis::cmd("HGET")
+                .arg(crawl_key(url))
+                .arg("task")
+                .query(con.deref_mut())?;
+            match data.map(Task::deserialise) {
+                Some(Ok(task)) => tasks.push(task),
+                Some(Err(e)) => warn!("Failed to deserialise the task of {} for recrawling. {}", redact_str(url), e),
+                None => warn!("The task of {} was missing when it was due for recrawling", redact_str(url)),
+            }
+        }
+
+        Ok(tasks)
+    }
+
+    /// Give back tasks that were taken but could not be submitted, such that they are due again
+    /// right away. Tasks that have been crawled again in the meantime keep their new due time.
+    pub fn restore(&self, tasks: &[Task]) -> RedisResult<()> {
+        let mut con = self.connection.lock().expect("Redis connection mutex was corrupted");
+
+        let now = get_timestamp_millis();
+        let mut pipeline = redis::pipe();
+        for task in tasks.iter() {
+            pipeline.cmd("ZADD").arg(RECRAWL_KEY).arg("NX").arg(now).arg(task.url.as_str()).ignore();
+        }
+        pipeline.query(con.deref_mut())
+    }
+}
+
+impl Scheduler for RedisScheduler {
+    /// Store that the page of the task was just crawled and schedule its next crawl
+    fn record_crawl(&self, task: &Task, content_hash: Option<u64>, validators: Option<&Validators>) -> ManagerResult<()> {
+        let mut con = self.connection.lock().expect("Redis connection mutex was corrupted");
+        self.schedule(con.deref_mut(), task, content_hash, validators).map_err(|e| {
+            ManagerError::new(UnreachableError, "Failed to schedule recrawl", Some(Box::new(e)))
+        })
+    }
+}
+
+impl RedisScheduler {
+    /// Update the crawl history and validators of the page of the task and add it to the recrawl
+    /// set by the time it is due. Validators that are missing are removed, as they no longer
+    /// apply to the page.
+    fn schedule(&self, con: &mut Connection, task: &Task, content_hash: Option<u64>, validators: Option<&Validators>) -> RedisResult<()> {
+        let key = crawl_key(task.url.as_str());
+
+        let (previous_hash, previous_interval): (Option<u64>, Option<u64>) = redis::cmd("HMGET")
+            .arg(key.as_str())
+            .arg("hash")
+            .arg("interval")
+            .query(con)?;
+        let previous = previous_hash.and_then(|hash| previous_interval.map(|interval| (hash, interval)));
+        // A page that was not modified still has the content of the previous crawl
+        let content_hash = content_hash.or(previous_hash).unwrap_or(0);
+        let changefreq = task.sitemap.as_ref().and_then(|sitemap| sitemap.changefreq);
+        let interval_ms = self.policy.next_interval_ms(previous, content_hash, changefreq);
+
+        // The task is recrawled as a fresh task
+        let mut recrawl_task = task.clone();
+        recrawl_task.attempts = 0;
+
+        let now = get_timestamp_millis();
+        let mut pipeline = redis::pipe();
+        pipeline.atomic()
+            .cmd("HSET").arg(key.as_str())
+            .arg("task").arg(recrawl_task.serialise())
+            .arg("last_crawl").arg(now)
+            .arg("hash").arg(content_hash)
+            .arg("interval").arg(interval_ms)
+            .ignore()
+            .cmd("ZADD").arg(RECRAWL_KEY).arg(now.saturating_add(interval_ms as i64)).arg(task.url.as_str())
+            .ignore();
+        if let Some(validators) = validators {
+            for (field, value) in [("etag", &validators.etag), ("last_modified", &validators.last_modified)].iter() {
+                match value {
+                    Some(value) => pipeline.cmd("HSET").arg(key.as_str()).arg(*field).arg(value.as_str()).ignore(),
+                    None => pipeline.cmd("HDEL").arg(key.as_str()).arg(*field).ignore(),
+                };
+            }
+        }
+        pipeline.query(con)
+    }
+}
+
+/// The FNV-1a hash function. Unlike the default hasher, its hashes are stable across builds,
+/// which is required to compare hashes stored by different workers.
+struct ContentHasher(u64);
+
+impl Hasher for ContentHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Hash the content of a page
+pub(crate) fn content_hash<S: Hash>(content: &S) -> u64 {
+    let mut hasher = ContentHasher(0xcbf2_9ce4_8422_2325);
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recrawl::scheduler::{content_hash, DAY_MS, HOUR_MS, RecrawlPolicy};
+    use crate::task::ChangeFrequency;
+
+    fn policy() -> RecrawlPolicy {
+        RecrawlPolicy {
+            default_interval_ms: DAY_MS,
+            min_interval_ms: HOUR_MS,
+            max_interval_ms: 30 * DAY_MS,
+        }
+    }
+
+    /// The first interval is hinted by the changefreq of the sitemap, within the limits
+    #[test]
+    fn initial_interval() {
+        assert_eq!(policy().next_interval_ms(None, 1, None), DAY_MS);
+        assert_eq!(policy().next_interval_ms(None, 1, Some(ChangeFrequency::Weekly)), 7 * DAY_MS);
+        assert_eq!(policy().next_interval_ms(None, 1, Some(ChangeFrequency::Always)), HOUR_MS);
+        assert_eq!(policy().next_interval_ms(None, 1, Some(ChangeFrequency::Yearly)), 30 * DAY_MS);
+    }
+
+    /// The interval is halved when the content changed and doubled when it did not
+    #[test]
+    fn adaptive_interval() {
+        assert_eq!(policy().next_interval_ms(Some((1, 4 * HOUR_MS)), 2, None), 2 * HOUR_MS);
+        assert_eq!(policy().next_interval_ms(Some((1, 4 * HOUR_MS)), 1, None), 8 * HOUR_MS);
+        assert_eq!(policy().next_interval_ms(Some((1, HOUR_MS)), 2, None), HOUR_MS);
+        assert_eq!(policy().next_interval_ms(Some((1, 30 * DAY_MS)), 1, None), 30 * DAY_MS);
+    }
+
+    #[test]
+    fn content_hash_differs() {
+        let page = b"<html>a</html>".to_vec();
+        assert_eq!(content_hash(&page), content_hash(&page.clone()));
+        assert_ne!(content_hash(&page), content_hash(&b"<html>b</html>".to_vec()));
+    }
+}
@@ -0,0 +1,72 @@
+use std::ops::DerefMut;
+use std::sync::Mutex;
+
+use redis::{Connection, RedisResult};
+use reqwest::header::{ETAG, HeaderMap, HeaderName, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::RequestBuilder;
+use url::Url;
+
+use crate::recrawl::scheduler::crawl_key;
+
+/// The validators of a page, which are sent when the page is downloaded again such that the
+/// server can answer that the page has not been modified instead of sending it again
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Read the validators of a response from its headers
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Validators {
+            etag: header_string(headers, ETAG),
+            last_modified: header_string(headers, LAST_MODIFIED),
+        }
+    }
+
+    /// Make the request conditional on the page having been modified since these validators
+    /// were received
+    pub fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+        request
+    }
+}
+
+/// Read a header as a string, if it is present and valid
+fn header_string(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers.get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+/// The RedisValidatorStore stores the validators of each page in Redis, alongside the crawl
+/// history of the page, such that every worker in the cluster can make conditional requests.
+pub struct RedisValidatorStore {
+    connection: Mutex<Connection>,
+}
+
+impl RedisValidatorStore {
+    /// Construct a new RedisValidatorStore
+    pub fn new(connection: Connection) -> Self {
+        RedisValidatorStore {
+            connection: Mutex::new(connection),
+        }
+    }
+
+    /// Get the validators stored for the page of the given url
+    pub fn get(&self, url: &Url) -> RedisResult<Validators> {
+        let mut con = self.connection.lock().expect("Redis connection mutex was corrupted");
+        let (etag, last_modified) = redis::cmd("HMGET")
+            .arg(crawl_key(url.as_str()))
+            .arg("etag")
+            .arg("last_modified")
+            .query(con.deref_mut())?;
+        Ok(Validators { etag, last_modified })
+    }
+}
@@ -1,56 +1,37 @@
 use std::sync::{Mutex};
 
-use futures::future::Future;
+use futures::future::{self, Future};
 use futures::stream::Stream;
-use lapin_futures::{BasicProperties, Channel, Client, ConnectionProperties, ExchangeKind, Queue};
+use lapin_futures::{BasicProperties, Channel, Client, ConfirmationFuture, ConnectionProperties, ExchangeKind, Queue};
 use lapin_futures::options::{
-    BasicConsumeOptions, BasicPublishOptions, BasicRejectOptions, ExchangeDeclareOptions,
+    BasicConsumeOptions, BasicGetOptions, BasicPublishOptions, BasicRejectOptions, ExchangeDeclareOptions,
     QueueBindOptions, QueueDeclareOptions, BasicQosOptions,
 };
-use lapin_futures::types::FieldTable;
-use redis::{Connection, ConnectionAddr, FromRedisValue, RedisError, RedisWrite, ToRedisArgs, Value, ConnectionInfo, IntoConnectionInfo, RedisResult, PipelineCommands};
+use lapin_futures::message::Delivery;
+use lapin_futures::types::{AMQPValue, FieldTable};
+use url::Url;
+use redis::{Connection, ConnectionAddr, RedisError, ConnectionInfo, IntoConnectionInfo, RedisResult, PipelineCommands};
 
+use crate::credentials::{redact_message, redact_url};
 use crate::errors::{ManagerError, ManagerResult};
-use crate::errors::ManagerErrorKind::UnreachableError;
+use crate::errors::ManagerErrorKind::{InvalidTask, UnreachableError};
 use crate::task::Task;
-use crate::traits::{Manager, TaskProcessResult};
+use crate::metrics::influx_client::get_timestamp_millis;
+use crate::partition::{ALL_PARTITIONS_ROUTING_KEY, partition_of, partition_queue_name, partition_routing_key, RoundRobin};
+use crate::traits::{Manager, TaskFailure, TaskProcessResult};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::ops::DerefMut;
 use std::borrow::BorrowMut;
 
-// Allows Redis to automatically serialise Task into raw bytes with type inference
-impl ToRedisArgs for &Task {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + RedisWrite,
-    {
-        out.write_arg(self.url.as_str().as_bytes())
-    }
-}
-
-// Allows Redis to automatically deserialise Task from raw bytes with type inference
-impl FromRedisValue for Task {
-    fn from_redis_value(v: &Value) -> Result<Self, RedisError> {
-        match *v {
-            Value::Data(ref bytes) => {
-                Task::deserialise(bytes.to_owned())
-                    .map_err(|_| RedisError::from(std::io::Error::new(std::io::ErrorKind::Other, "Failed to deserialise task")))
-            }
-            _ => Err(RedisError::from(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Response could not be translated to a task",
-            )))
-        }
-    }
-}
-
 /// Error that encapsulates potential errors during construction of a RMQRedisManager.
 /// It enables us to return detailed error messages.
 #[derive(Debug)]
 pub enum RMQRedisManagerError {
     RedisError(RedisError),
     RabbitMQError(lapin_futures::Error),
+    /// The frontier has another number of partitions than the worker was given
+    PartitionMismatch { recorded: u32, given: u32 },
 }
 
 impl Error for RMQRedisManagerError {
@@ -58,13 +39,19 @@ impl Error for RMQRedisManagerError {
         match self {
             RMQRedisManagerError::RedisError(e) => Some(e),
             RMQRedisManagerError::RabbitMQError(e) => Some(e),
+            RMQRedisManagerError::PartitionMismatch { .. } => None,
         }
     }
 }
 
 impl Display for RMQRedisManagerError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            RMQRedisManagerError::PartitionMismatch { recorded, given } => write!(
+                f, "The frontier has {} partitions, but {} partitions were given", recorded, given,
+            ),
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
@@ -80,41 +67,290 @@ impl From<lapin_futures::Error> for RMQRedisManagerError {
     }
 }
 
+// Names of the message headers that describe why a task was dead-lettered
+const ATTEMPTS_HEADER: &str = "attempts";
+const ERROR_KIND_HEADER: &str = "error_kind";
+const ERROR_MESSAGE_HEADER: &str = "error_message";
+const WORKER_HEADER: &str = "worker";
+const TIMESTAMP_HEADER: &str = "timestamp";
+
+/// The delays of the queues of postponed tasks in milliseconds, from a second to a day
+const POSTPONE_DELAYS_MS: [u64; 12] = [
+    1_000, 2_000, 5_000, 10_000, 30_000, 60_000, 300_000, 900_000, 1_800_000, 3_600_000, 21_600_000, 86_400_000,
+];
+
+/// The largest message TTL accepted by RabbitMQ, in milliseconds
+pub const MAX_MESSAGE_TTL_MS: u64 = 4_294_967_295;
+
+/// The RetryPolicy describes how rejected tasks are retried. A rejected task is retried after a
+/// delay that doubles with every attempt, until it has been attempted `max_attempts` times.
+/// A Retry-After of the failure is honoured up to `max_retry_after_ms`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_retry_after_ms: u64,
+}
+
+impl RetryPolicy {
+    /// The number of delayed retries a task can get before it is dropped
+    pub fn retries(&self) -> u32 {
+        self.max_attempts.saturating_sub(1)
+    }
+
+    /// The delay in milliseconds before the given retry, where the first retry is retry 0. The
+    /// delay is capped to the largest message TTL.
+    pub fn delay_ms(&self, retry: u32) -> u64 {
+        self.base_delay_ms.saturating_mul(2u64.saturating_pow(retry)).min(MAX_MESSAGE_TTL_MS)
+    }
+
+    /// The Retry-After to honour for a failure. A Retry-After longer than the maximum is ignored,
+    /// such that the task is retried like any other rejected task
+    pub fn retry_after_ms(&self, retry_after_ms: Option<u64>) -> Option<u64> {
+        retry_after_ms.filter(|delay_ms| *delay_ms <= self.max_retry_after_ms)
+    }
+
+    /// Check that every task is attempted at least once, and that the delay is a valid TTL
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err(String::from("The number of attempts must be at least 1"));
+        }
+        if self.base_delay_ms == 0 || self.base_delay_ms > MAX_MESSAGE_TTL_MS {
+            return Err(format!("The retry delay must be between 1 and {} ms", MAX_MESSAGE_TTL_MS));
+        }
+        Ok(())
+    }
+}
+
+/// Read the number of attempts from the headers of a dead-lettered message
+fn get_attempts(properties: &BasicProperties) -> u32 {
+    properties.headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(ATTEMPTS_HEADER))
+        .and_then(|value| match value {
+            AMQPValue::ShortShortUInt(n) => Some(u32::from(*n)),
+            AMQPValue::ShortUInt(n) => Some(u32::from(*n)),
+            AMQPValue::LongUInt(n) => Some(*n),
+            AMQPValue::LongInt(n) => Some(*n as u32),
+            AMQPValue::LongLongInt(n) => Some(*n as u32),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// The failure without the username and password of the task url, which the message of the
+/// failure may mention, such that they do not end up in the dead-letter queue
+fn redact_failure(failure: TaskFailure, url: &Url) -> TaskFailure {
+    TaskFailure {
+        msg: redact_message(&failure.msg, url),
+        ..failure
+    }
+}
+
+/// Read a string header from the headers of a message
+fn get_string_header(properties: &BasicProperties, name: &str) -> Option<String> {
+    properties.headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(name))
+        .and_then(|value| match value {
+            AMQPValue::LongString(s) => Some(s.to_string()),
+            AMQPValue::ShortString(s) => Some(s.to_string()),
+            _ => None,
+        })
+}
+
+/// Deserialise the task of a message
+fn deserialise_delivery(delivery: &Delivery) -> ManagerResult<Task> {
+    Task::deserialise(delivery.data.clone()).map_err(|e| {
+        ManagerError::new(InvalidTask, "Failed to deserialise task", Some(Box::new(e)))
+    })
+}
+
+/// A DeadLetter is an entry of the dead-letter queue, i.e. a task that failed permanently
+/// along with the reason it failed.
+#[derive(Debug)]
+pub struct DeadLetter {
+    pub data: Vec<u8>,
+    pub error_kind: Option<String>,
+    pub error_message: Option<String>,
+    pub worker: Option<String>,
+    pub attempts: u32,
+    pub timestamp: Option<i64>,
+}
+
+impl From<&Delivery> for DeadLetter {
+    fn from(delivery: &Delivery) -> Self {
+        let properties = &delivery.properties;
+        DeadLetter {
+            data: delivery.data.clone(),
+            error_kind: get_string_header(properties, ERROR_KIND_HEADER),
+            error_message: get_string_header(properties, ERROR_MESSAGE_HEADER),
+            worker: get_string_header(properties, WORKER_HEADER),
+            attempts: get_attempts(properties),
+            timestamp: properties.headers()
+                .as_ref()
+                .and_then(|headers| headers.inner().get(TIMESTAMP_HEADER))
+                .and_then(|value| match value {
+                    AMQPValue::LongLongInt(n) => Some(*n),
+                    _ => None,
+                }),
+        }
+    }
+}
+
+/// The arguments of the frontier queue. A frontier with a maximum priority serves tasks of
+/// higher priority first.
+fn frontier_queue_arguments(max_priority: u8) -> FieldTable {
+    let mut arguments = FieldTable::default();
+    if max_priority > 0 {
+        arguments.insert("x-max-priority".into(), AMQPValue::ShortShortUInt(max_priority));
+    }
+    arguments
+}
+
+/// The properties of the message of a task. The priority of the task is capped to the maximum
+/// priority of the frontier, and is left out if the frontier has no priorities.
+fn task_properties(task: &Task, max_priority: u8) -> BasicProperties {
+    match task.priority {
+        Some(priority) if max_priority > 0 => BasicProperties::default().with_priority(priority.min(max_priority)),
+        _ => BasicProperties::default(),
+    }
+}
+
+/// The delay of the queue that a task postponed by the given delay waits in, i.e. the shortest
+/// delay that is at least as long, or the longest delay
+fn postpone_delay_ms(delay_ms: u64) -> u64 {
+    let longest = POSTPONE_DELAYS_MS[POSTPONE_DELAYS_MS.len() - 1];
+    POSTPONE_DELAYS_MS.iter().cloned().find(|&delay| delay >= delay_ms).unwrap_or(longest)
+}
+
+/// Name of the queue that holds tasks postponed by the given delay
+fn postponed_queue_name(frontier_queue_name: &str, delay_ms: u64) -> String {
+    format!("{}.postponed.{}", frontier_queue_name, delay_ms)
+}
+
+/// Name of the Redis key that holds the number of partitions of the frontier
+fn partitions_key(frontier_queue_name: &str) -> String {
+    format!("{}:partitions", frontier_queue_name)
+}
+
+/// Name of the Redis hash from each canonical Url to the task that first declared it. Crawls
+/// sharing a Redis instance have their own collection sets, and thereby their own canonical Urls
+fn canonical_key(redis_set: &str) -> String {
+    format!("{}:canonical", redis_set)
+}
+
+/// Name of the queue that holds tasks waiting for a retry with the given delay. The TTL of an
+/// existing queue cannot be changed, so the queues are named by their delay
+fn retry_queue_name(frontier_queue_name: &str, delay_ms: u64) -> String {
+    format!("{}.retry.{}", frontier_queue_name, delay_ms)
+}
+
+/// The number of unacknowledged tasks a worker may hold from each partition of the frontier.
+/// The prefetch count is shared among the partitions, but every partition may hold at least one
+/// task, such that no partition is left out.
+fn partition_prefetch_count(prefetch_count: u16, partitions: u32) -> u16 {
+    (u32::from(prefetch_count) / partitions.max(1)).max(1) as u16
+}
+
+/// The addresses, names and policies a RMQRedisManager is constructed with
+#[derive(Debug)]
+pub struct RMQRedisManagerConfig<'a> {
+    pub rmq_addr: String,
+    pub rmq_port: u16,
+    pub redis_addr: String,
+    pub redis_port: u16,
+    pub exchange: String,
+    pub prefetch_count: u16,
+    pub frontier_queue_name: String,
+    pub collection_queue_name: String,
+    pub redis_set: String,
+    pub sentinel: Option<&'a str>,
+    pub concurrency: usize,
+    pub retry_policy: RetryPolicy,
+    pub dead_letter_queue_name: String,
+    pub worker_name: String,
+    pub max_priority: u8,
+    pub partitions: u32,
+}
+
 /// The RMQRedisManager is a Manager for a distributed web crawler that uses RabbitMQ and Redis.
-/// Tasks are submitted to a RMQ exchange and received from a RMQ queue.
+/// Tasks are submitted to a RMQ exchange and received from RMQ queues.
 /// When checking if a task has already been submitted, the RQMRedisManager will ask Redis if
 /// the task is in a given set.
+///
+/// The frontier is partitioned by host, i.e. the exchange routes each task to the queue of the
+/// partition of its host. Workers take turns receiving tasks from each partition and hold a
+/// limited number of tasks of each partition at once, such that a host with a huge link graph
+/// only gets its share of the crawl.
 pub struct RMQRedisManager {
     rmq_addr: String,
     rmq_port: u16,
     redis_addr: String,
     redis_port: u16,
     channel: Channel,
-    frontier_queue: Queue,
+    frontier_queues: Vec<Queue>,
+    frontier_queue_name: String,
+    partitions: u32,
     exchange: String,
     prefetch_count: u16,
     redis_connection: Mutex<Connection>,
     redis_set: String,
+    concurrency: usize,
+    retry_policy: RetryPolicy,
+    dead_letter_queue_name: String,
+    worker_name: String,
+    max_priority: u8,
 }
 
 impl RMQRedisManager {
     /// Construct a new RMQRedisManager
-    pub fn new(
-        rmq_addr: String,
-        rmq_port: u16,
-        redis_addr: String,
-        redis_port: u16,
-        exchange: String,
-        prefetch_count: u16,
-        frontier_queue_name: String,
-        collection_queue_name: String,
-        redis_set: String,
-        sentinel: Option<&str>,
-    ) -> Result<RMQRedisManager, RMQRedisManagerError> {
-        debug!("Creating RMQRedisManager with following values: \n\trmq_addr: {:?}\n\trmq_port: {:?}\
-            \n\t redis_addr: {:?}\n\tredis_port: {:?}\n\trmq_exchange: {:?}\n\tprefetch_count: {:?}\
-            \n\trmq_queue_name: {:?}\n\tcollection_queue_name: {:?}\n\tredis_set: {:?}\n\tsentinel: {:?}"
-               , rmq_addr, rmq_port, redis_addr, redis_port, exchange, prefetch_count, frontier_queue_name, collection_queue_name, redis_set, sentinel);
+    pub fn new(config: RMQRedisManagerConfig) -> Result<RMQRedisManager, RMQRedisManagerError> {
+        debug!("Creating RMQRedisManager with following values: {:#?}", config);
+        let RMQRedisManagerConfig {
+            rmq_addr,
+            rmq_port,
+            redis_addr,
+            redis_port,
+            exchange,
+            prefetch_count,
+            frontier_queue_name,
+            collection_queue_name,
+            redis_set,
+            sentinel,
+            concurrency,
+            retry_policy,
+            dead_letter_queue_name,
+            worker_name,
+            max_priority,
+            partitions,
+        } = config;
+
+        // At least one thread is needed to resolve tasks, and one partition to hold them
+        let concurrency = concurrency.max(1);
+        let partitions = partitions.max(1);
+
+        // Tasks beyond the prefetch count are never received, so extra threads would stay idle
+        if concurrency > prefetch_count as usize {
+            warn!("Concurrency ({}) is larger than the prefetch count ({}). Some threads will be idle",
+                  concurrency, prefetch_count);
+        }
+
+        // Establish Redis connection
+        let connection_info = format!("redis://{}:{}/", redis_addr, redis_port).as_str()
+            .into_connection_info()?;
+        let mut redis_connection = create_redis_connection(connection_info, sentinel)?;
+
+        // Workers must agree on the number of partitions, or tasks are routed to partitions that
+        // no worker receives from. The first worker records the number of partitions
+        let (recorded,): (u32,) = redis::pipe()
+            .atomic()
+            .set_nx(partitions_key(frontier_queue_name.as_str()), partitions).ignore()
+            .get(partitions_key(frontier_queue_name.as_str()))
+            .query(&mut redis_connection)?;
+        if recorded != partitions {
+            return Err(RMQRedisManagerError::PartitionMismatch { recorded, given: partitions });
+        }
 
         let client = Client::connect(
             format!("amqp://{}:{}/%2f", rmq_addr, rmq_port).as_str(),
@@ -123,53 +359,99 @@ impl RMQRedisManager {
 
         let channel = client.create_channel().wait()?;
 
-        let frontier_queue = channel.queue_declare(
-            frontier_queue_name.as_str(),
-            QueueDeclareOptions::default(),
-            FieldTable::default(),
-        ).wait()?;
-
         channel.queue_declare(
             collection_queue_name.as_str(),
             QueueDeclareOptions::default(),
             FieldTable::default(),
         ).wait()?;
 
+        // Tasks are routed to the partition of their host. The kind of an existing exchange
+        // cannot be changed, so an exchange of another kind must be deleted first
         channel.exchange_declare(
             exchange.as_str(),
-            ExchangeKind::Fanout,
+            ExchangeKind::Topic,
             ExchangeDeclareOptions::default(),
             FieldTable::default(),
         ).wait()?;
 
+        // The collection receives the tasks of every partition
         channel.queue_bind(
-            frontier_queue_name.as_str(),
+            collection_queue_name.as_str(),
             exchange.as_str(),
-            "",
+            ALL_PARTITIONS_ROUTING_KEY,
             QueueBindOptions::default(),
             FieldTable::default(),
         ).wait()?;
 
-        channel.queue_bind(
-            collection_queue_name.as_str(),
-            exchange.as_str(),
-            "",
-            QueueBindOptions::default(),
+        channel.queue_declare(
+            dead_letter_queue_name.as_str(),
+            QueueDeclareOptions::default(),
             FieldTable::default(),
         ).wait()?;
 
-        // Limit the amount of tasks stored in the local queue
+        let mut frontier_queues = Vec::new();
+        for partition in 0..partitions {
+            let queue_name = partition_queue_name(frontier_queue_name.as_str(), partition, partitions);
+
+            // The maximum priority of a queue cannot be changed once declared, so an existing
+            // frontier queue must be deleted before the maximum priority is changed
+            frontier_queues.push(channel.queue_declare(
+                queue_name.as_str(),
+                QueueDeclareOptions::default(),
+                frontier_queue_arguments(max_priority),
+            ).wait()?);
+
+            channel.queue_bind(
+                queue_name.as_str(),
+                exchange.as_str(),
+                partition_routing_key(partition).as_str(),
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            ).wait()?;
+
+            // Declare a queue for each delay of postponed tasks. Messages only expire at the head
+            // of a queue, so every task of a queue must have the same delay. Tasks wait in the
+            // queue until the delay expires, after which they are dead-lettered back into the
+            // queue of their partition
+            for delay_ms in POSTPONE_DELAYS_MS.iter() {
+                let mut arguments = FieldTable::default();
+                arguments.insert("x-message-ttl".into(), AMQPValue::LongLongInt(*delay_ms as i64));
+                arguments.insert("x-dead-letter-exchange".into(), AMQPValue::LongString("".into()));
+                arguments.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(queue_name.as_str().into()));
+
+                channel.queue_declare(
+                    postponed_queue_name(queue_name.as_str(), *delay_ms).as_str(),
+                    QueueDeclareOptions::default(),
+                    arguments,
+                ).wait()?;
+            }
+
+            // Declare a queue for each retry. Tasks wait in a retry queue until their delay expires,
+            // after which they are dead-lettered back into the queue of their partition
+            for retry in 0..retry_policy.retries() {
+                let delay_ms = retry_policy.delay_ms(retry);
+                let mut arguments = FieldTable::default();
+                arguments.insert("x-message-ttl".into(), AMQPValue::LongLongInt(delay_ms as i64));
+                arguments.insert("x-dead-letter-exchange".into(), AMQPValue::LongString("".into()));
+                arguments.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(queue_name.as_str().into()));
+
+                channel.queue_declare(
+                    retry_queue_name(queue_name.as_str(), delay_ms).as_str(),
+                    QueueDeclareOptions::default(),
+                    arguments,
+                ).wait()?;
+            }
+        }
+
+        // Limit the amount of tasks stored in the local queue, both in total and per partition
         channel.basic_qos(
             prefetch_count,
-            BasicQosOptions::default(),
+            BasicQosOptions { global: true },
+        ).wait()?;
+        channel.basic_qos(
+            partition_prefetch_count(prefetch_count, partitions),
+            BasicQosOptions { global: false },
         ).wait()?;
-
-        // Establish Redis connection
-        let connection_info = format!("redis://{}:{}/", redis_addr, redis_port).as_str()
-            .into_connection_info()?;
-        let redis_connection = Mutex::new(
-            create_redis_connection(connection_info, sentinel)?
-        );
 
         Ok(RMQRedisManager {
             rmq_addr,
@@ -177,13 +459,295 @@ impl RMQRedisManager {
             redis_addr,
             redis_port,
             channel,
-            frontier_queue,
+            frontier_queues,
+            frontier_queue_name,
+            partitions,
             exchange,
             prefetch_count,
-            redis_connection,
+            redis_connection: Mutex::new(redis_connection),
             redis_set,
+            concurrency,
+            retry_policy,
+            dead_letter_queue_name,
+            worker_name,
+            max_priority,
         })
     }
+
+    /// Name of the queue of the partition of the given task
+    fn task_queue_name(&self, task: &Task) -> String {
+        partition_queue_name(self.frontier_queue_name.as_str(), partition_of(&task.url, self.partitions), self.partitions)
+    }
+
+    /// Names of the queues of every partition of the frontier
+    pub fn partition_queue_names(&self) -> Vec<String> {
+        (0..self.partitions)
+            .map(|partition| partition_queue_name(self.frontier_queue_name.as_str(), partition, self.partitions))
+            .collect()
+    }
+
+    /// The data, queue, and properties to publish the task of a delivery with, such that it is
+    /// routed to the partition of its host. Tasks that cannot be deserialised are given to the
+    /// first partition, as their host is unknown. The previous attempts of the task are forgotten
+    /// if `reset_attempts` is true.
+    fn partition_delivery(&self, delivery: &Delivery, reset_attempts: bool) -> (Vec<u8>, String, BasicProperties) {
+        match deserialise_delivery(delivery) {
+            Ok(mut task) => {
+                if reset_attempts {
+                    task.attempts = 0;
+                }
+                (task.serialise(), self.task_queue_name(&task), task_properties(&task, self.max_priority))
+            }
+            Err(_) => (
+                delivery.data.clone(),
+                partition_queue_name(self.frontier_queue_name.as_str(), 0, self.partitions),
+                BasicProperties::default(),
+            ),
+        }
+    }
+
+    /// Publish a rejected task to the retry queue matching its number of attempts. The task
+    /// returns to the frontier once the delay of the retry queue has expired. If the failure asks
+    /// for a longer delay than the retry queue, the task is postponed by that delay instead.
+    fn retry_task(&self, task: &Task, attempts: u32, retry_after_ms: Option<u64>) -> ManagerResult<()> {
+        let mut task = task.clone();
+        task.attempts = attempts;
+
+        if let Some(retry_after_ms) = retry_after_ms {
+            if retry_after_ms > self.retry_policy.delay_ms(attempts - 1) {
+                return self.postpone_task(&task, retry_after_ms);
+            }
+        }
+
+        self.channel
+            .basic_publish(
+                // The default exchange routes directly to the queue with the routing key's name
+                "",
+                retry_queue_name(self.task_queue_name(&task).as_str(), self.retry_policy.delay_ms(attempts - 1)).as_str(),
+                task.serialise(),
+                BasicPublishOptions::default(),
+                task_properties(&task, self.max_priority),
+            )
+            .wait()
+            .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))
+    }
+
+    /// Resolve the task of a single delivery and acknowledge or reject the delivery depending
+    /// on the result.
+    fn resolve_delivery(&self, msg: Delivery, resolve_func: &(dyn Fn(Task) -> TaskProcessResult + Sync)) {
+        let result = match deserialise_delivery(&msg) {
+            Err(e) => {
+                // Deserialisation failed. Discard the task
+                info!("Discarded task due to failed deserialisation");
+                self.discard_delivery(&msg, &TaskFailure::from(&e), 1)
+            }
+            Ok(task) => {
+                let attempts = task.attempts + 1;
+                let url = redact_url(&task.url);
+                // Resolve task
+                match resolve_func(task.clone()) {
+                    TaskProcessResult::Ok => {
+                        self.channel.basic_ack(msg.delivery_tag, false)
+                    }
+                    TaskProcessResult::Err(failure) => {
+                        info!("Discarded task {}", url);
+                        // Do not requeue task if error is met
+                        self.discard_delivery(&msg, &redact_failure(failure, &task.url), attempts)
+                    },
+                    TaskProcessResult::Reject(failure) => {
+                        if attempts < self.retry_policy.max_attempts {
+                            let retry_after_ms = self.retry_policy.retry_after_ms(failure.retry_after_ms);
+                            if retry_after_ms != failure.retry_after_ms {
+                                warn!("Ignored Retry-After of {:?} ms for task {}", failure.retry_after_ms, url);
+                            }
+                            let delay_ms = self.retry_policy.delay_ms(attempts - 1).max(retry_after_ms.unwrap_or(0));
+                            info!("Rejected task {}. Retrying in {} ms (attempt {} of {})",
+                                  url, delay_ms, attempts, self.retry_policy.max_attempts);
+                            match self.retry_task(&task, attempts, retry_after_ms) {
+                                Ok(_) => self.channel.basic_ack(msg.delivery_tag, false),
+                                Err(e) => {
+                                    error!("Failed to schedule retry of task {}. {}", url, e);
+                                    // Requeue task immediately rather than losing it
+                                    self.channel.basic_reject(msg.delivery_tag, BasicRejectOptions { requeue: true })
+                                }
+                            }
+                        } else {
+                            info!("Discarded task {} after {} attempts", url, attempts);
+                            // Do not requeue task when it has no attempts left
+                            self.discard_delivery(&msg, &redact_failure(failure, &task.url), attempts)
+                        }
+                    },
+                    TaskProcessResult::Postpone(delay_ms) => {
+                        debug!("Postponed task {} for {} ms", url, delay_ms);
+                        match self.postpone_task(&task, delay_ms) {
+                            Ok(_) => self.channel.basic_ack(msg.delivery_tag, false),
+                            Err(e) => {
+                                error!("Failed to postpone task {}. {}", url, e);
+                                self.channel.basic_reject(msg.delivery_tag, BasicRejectOptions { requeue: true })
+                            }
+                        }
+                    },
+                }
+            }
+        };
+
+        if let Err(e) = result.wait() {
+            error!("Failed to acknowledge delivery {}. {}", msg.delivery_tag, e);
+        }
+    }
+
+    /// Publish a task to a postponed queue, from where it returns to the frontier after at least
+    /// the given delay, or a day if the delay is longer. The number of previous attempts is kept.
+    fn postpone_task(&self, task: &Task, delay_ms: u64) -> ManagerResult<()> {
+        self.channel
+            .basic_publish(
+                "",
+                postponed_queue_name(self.task_queue_name(task).as_str(), postpone_delay_ms(delay_ms)).as_str(),
+                task.serialise(),
+                BasicPublishOptions::default(),
+                task_properties(task, self.max_priority),
+            )
+            .wait()
+            .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))
+    }
+
+    /// Move a delivery that failed permanently to the dead-letter queue, along with headers
+    /// describing the failure. If the dead-letter queue cannot be reached, the delivery is
+    /// rejected and the task is lost.
+    fn discard_delivery(&self, msg: &Delivery, failure: &TaskFailure, attempts: u32) -> ConfirmationFuture<()> {
+        let mut headers = FieldTable::default();
+        headers.insert(ERROR_KIND_HEADER.into(), AMQPValue::LongString(failure.kind.as_str().into()));
+        headers.insert(ERROR_MESSAGE_HEADER.into(), AMQPValue::LongString(failure.msg.as_str().into()));
+        headers.insert(WORKER_HEADER.into(), AMQPValue::LongString(self.worker_name.as_str().into()));
+        headers.insert(ATTEMPTS_HEADER.into(), AMQPValue::LongUInt(attempts));
+        headers.insert(TIMESTAMP_HEADER.into(), AMQPValue::LongLongInt(get_timestamp_millis()));
+
+        let result = self.channel
+            .basic_publish(
+                "",
+                self.dead_letter_queue_name.as_str(),
+                msg.data.clone(),
+                BasicPublishOptions::default(),
+                BasicProperties::default().with_headers(headers),
+            )
+            .wait();
+
+        match result {
+            Ok(_) => self.channel.basic_ack(msg.delivery_tag, false),
+            Err(e) => {
+                error!("Failed to publish delivery {} to the dead-letter queue. {}", msg.delivery_tag, e);
+                self.channel.basic_reject(msg.delivery_tag, BasicRejectOptions { requeue: false })
+            }
+        }
+    }
+
+    /// Fetch up to `limit` entries from the dead-letter queue. The fetched entries are left in
+    /// the dead-letter queue.
+    pub fn get_dead_letters(&self, limit: usize) -> ManagerResult<Vec<DeadLetter>> {
+        let deliveries = self.fetch_dead_letters(limit)?;
+        let dead_letters = deliveries.iter().map(DeadLetter::from).collect();
+
+        // Return every entry to the dead-letter queue
+        for delivery in deliveries.iter() {
+            self.channel.basic_reject(delivery.delivery_tag, BasicRejectOptions { requeue: true })
+                .wait()
+                .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))?;
+        }
+
+        Ok(dead_letters)
+    }
+
+    /// Move the entries accepted by `predicate` from the dead-letter queue back to the frontier.
+    /// At most `limit` entries of the dead-letter queue are considered. Returns the number of
+    /// entries moved.
+    pub fn requeue_dead_letters(&self, predicate: &dyn Fn(&DeadLetter) -> bool, limit: usize) -> ManagerResult<usize> {
+        let deliveries = self.fetch_dead_letters(limit)?;
+        let mut requeued = 0;
+
+        for delivery in deliveries.iter() {
+            let result = if predicate(&DeadLetter::from(delivery)) {
+                // The task starts over with no previous attempts
+                let (data, queue_name, properties) = self.partition_delivery(delivery, true);
+                let published = self.channel
+                    .basic_publish(
+                        "",
+                        queue_name.as_str(),
+                        data,
+                        BasicPublishOptions::default(),
+                        properties,
+                    )
+                    .wait();
+
+                match published {
+                    Ok(_) => {
+                        requeued += 1;
+                        self.channel.basic_ack(delivery.delivery_tag, false).wait()
+                    }
+                    Err(e) => Err(e),
+                }
+            } else {
+                self.channel.basic_reject(delivery.delivery_tag, BasicRejectOptions { requeue: true }).wait()
+            };
+
+            result.map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))?;
+        }
+
+        Ok(requeued)
+    }
+
+    /// Move every task of the given queue into the partition of its host, e.g. the tasks of the
+    /// frontier queue of a version that did not partition the frontier. Returns the number of
+    /// tasks moved.
+    pub fn drain_queue(&self, queue_name: &str) -> ManagerResult<usize> {
+        let mut drained = 0;
+        loop {
+            let message = self.channel
+                .basic_get(queue_name, BasicGetOptions { no_ack: false })
+                .wait()
+                .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))?;
+            let delivery = match message {
+                Some(message) => message.delivery,
+                // The queue is empty
+                None => return Ok(drained),
+            };
+
+            let (data, partition_queue_name, properties) = self.partition_delivery(&delivery, false);
+            self.channel
+                .basic_publish(
+                    "",
+                    partition_queue_name.as_str(),
+                    data,
+                    BasicPublishOptions::default(),
+                    properties,
+                )
+                .wait()
+                .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))?;
+            self.channel.basic_ack(delivery.delivery_tag, false)
+                .wait()
+                .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))?;
+            drained += 1;
+        }
+    }
+
+    /// Fetch up to `limit` deliveries from the dead-letter queue without acknowledging them.
+    /// Unacknowledged deliveries are not fetched again, so each entry is fetched at most once.
+    fn fetch_dead_letters(&self, limit: usize) -> ManagerResult<Vec<Delivery>> {
+        let mut deliveries = Vec::new();
+        while deliveries.len() < limit {
+            let message = self.channel
+                .basic_get(self.dead_letter_queue_name.as_str(), BasicGetOptions { no_ack: false })
+                .wait()
+                .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))?;
+
+            match message {
+                Some(message) => deliveries.push(message.delivery),
+                // The dead-letter queue is empty
+                None => break,
+            }
+        }
+
+        Ok(deliveries)
+    }
 }
 
 impl Manager for RMQRedisManager {
@@ -194,10 +758,10 @@ impl Manager for RMQRedisManager {
             let result = self.channel
                 .basic_publish(
                     self.exchange.as_str(),
-                    "",
+                    partition_routing_key(partition_of(&task.url, self.partitions)).as_str(),
                     task.serialise(),
                     BasicPublishOptions::default(),
-                    BasicProperties::default(),
+                    task_properties(task, self.max_priority),
                 )
                 .wait();
 
@@ -209,51 +773,44 @@ impl Manager for RMQRedisManager {
         Ok(())
     }
 
-    /// Start resolving tasks with the given resolve function
-    fn subscribe(&self, resolve_func: &dyn Fn(Task) -> TaskProcessResult) {
-        self.channel
-            .basic_consume(
-                &self.frontier_queue,
-                "",
-                BasicConsumeOptions::default(),
-                FieldTable::default(),
-            )
-            .and_then(move |consumer| {
-                // Resolve each message received
-                consumer.for_each(move |msg| {
-                    match Task::deserialise(msg.data) {
-                        Err(_) => {
-                            // Deserialisation failed. Discard the task
-                            info!("Discarded task due to failed deserialisation");
-                            self.channel.basic_reject(msg.delivery_tag, BasicRejectOptions { requeue: false })
-                        }
-                        Ok(task) => {
-                            // Resolve task
-                            match resolve_func(task.clone()) {
-                                TaskProcessResult::Ok => {
-                                    self.channel.basic_ack(msg.delivery_tag, false)
-                                }
-                                TaskProcessResult::Err => {
-                                    info!("Discarded task {}", task.url);
-                                    self
-                                        .channel
-                                        // Do not requeue task if error is met
-                                        .basic_reject(msg.delivery_tag, BasicRejectOptions { requeue: false })
-                                },
-                                TaskProcessResult::Reject => {
-                                    info!("Rejected task {}", task.url);
-                                    self
-                                        .channel
-                                        // Requeue task if error is met
-                                        .basic_reject(msg.delivery_tag, BasicRejectOptions { requeue: false })
-                                },
-                            }
-                        }
+    /// Start resolving tasks with the given resolve function. Received tasks are distributed
+    /// to a pool of threads, such that up to `concurrency` tasks are resolved at once.
+    fn subscribe(&self, resolve_func: &(dyn Fn(Task) -> TaskProcessResult + Sync)) {
+        // Deliveries are handed to the resolving threads through a bounded channel, such that
+        // the consumer waits while every thread is busy
+        let (sender, receiver) = crossbeam::channel::bounded::<Delivery>(self.concurrency);
+
+        crossbeam::scope(|scope| {
+            for _ in 0..self.concurrency {
+                let receiver = receiver.clone();
+                scope.spawn(move |_| {
+                    // Resolve messages until the consumer stops and the channel is disconnected
+                    for msg in receiver.iter() {
+                        self.resolve_delivery(msg, resolve_func);
                     }
+                });
+            }
+
+            let consumers = self.frontier_queues.iter().map(|queue| {
+                self.channel.basic_consume(
+                    queue,
+                    "",
+                    BasicConsumeOptions::default(),
+                    FieldTable::default(),
+                )
+            });
+            future::join_all(consumers)
+                .and_then(move |consumers| {
+                    // Take turns receiving messages from each partition, and pass each message
+                    // received on to the resolving threads
+                    RoundRobin::new(consumers).for_each(move |msg| {
+                        sender.send(msg).expect("All task resolving threads have stopped");
+                        future::ok(())
+                    })
                 })
-            })
-            .wait()
-            .unwrap();
+                .wait()
+                .unwrap();
+        }).expect("A task resolving thread panicked");
     }
 
     /// Closes the manager and its connections
@@ -271,7 +828,7 @@ impl Manager for RMQRedisManager {
         let reset_set = self.redis_set.as_str();
         let mut pipeline = redis::pipe();
         for task in tasks.iter() {
-            pipeline.sismember(reset_set, task);
+            pipeline.sismember(reset_set, task.url.as_str());
         }
         let is_member_vec: Vec<bool> = pipeline.query(con.deref_mut())
             .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))?;
@@ -288,11 +845,28 @@ impl Manager for RMQRedisManager {
             })
             .collect())
     }
+
+    /// Record the canonical Url of the page of a task. The first task to declare a canonical Url
+    /// owns it, such that a recrawl of the same task is not taken for a duplicate. The canonical
+    /// Url is added to the collection, so links to it are culled.
+    fn record_canonical(&self, task: &Task, canonical: &Url) -> ManagerResult<bool> {
+        let mut con = self.redis_connection.lock().expect("Redis connection mutex was corrupted");
+
+        let (owner,): (String,) = redis::pipe()
+            .atomic()
+            .hset_nx(canonical_key(self.redis_set.as_str()), canonical.as_str(), task.url.as_str()).ignore()
+            .hget(canonical_key(self.redis_set.as_str()), canonical.as_str())
+            .sadd(self.redis_set.as_str(), canonical.as_str()).ignore()
+            .query(con.deref_mut())
+            .map_err(|e| ManagerError::new(UnreachableError, "Could not reach manager.", Some(Box::new(e))))?;
+
+        Ok(owner == task.url.as_str())
+    }
 }
 
 /// Establishes a redis connection. An optional name of a master group can be given to
 /// make the connection sentinel.
-fn create_redis_connection(connection_info: ConnectionInfo, sentinel: Option<&str>) -> Result<Connection, RedisError> {
+pub(crate) fn create_redis_connection(connection_info: ConnectionInfo, sentinel: Option<&str>) -> Result<Connection, RedisError> {
     let mut client = redis::Client::open(connection_info.clone())?;
 
     if let Some(name) = sentinel {
@@ -317,3 +891,167 @@ fn create_redis_connection(connection_info: ConnectionInfo, sentinel: Option<&st
         return client.get_connection()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lapin_futures::BasicProperties;
+    use lapin_futures::message::Delivery;
+    use lapin_futures::types::{AMQPValue, FieldTable};
+
+    use url::Url;
+
+    use crate::rmqredis::{ATTEMPTS_HEADER, canonical_key, DeadLetter, ERROR_KIND_HEADER, ERROR_MESSAGE_HEADER, get_attempts, MAX_MESSAGE_TTL_MS, partition_prefetch_count, partitions_key, postpone_delay_ms, postponed_queue_name, retry_queue_name, RetryPolicy, task_properties, TIMESTAMP_HEADER, WORKER_HEADER};
+    use crate::task::Task;
+
+    /// The delay doubles with every retry
+    #[test]
+    fn retry_policy_delay_doubles() {
+        let policy = RetryPolicy { max_attempts: 4, base_delay_ms: 1000, max_retry_after_ms: 3_600_000 };
+        assert_eq!(policy.delay_ms(0), 1000);
+        assert_eq!(policy.delay_ms(1), 2000);
+        assert_eq!(policy.delay_ms(2), 4000);
+    }
+
+    /// The delay is capped to the largest TTL instead of overflowing
+    #[test]
+    fn retry_policy_delay_saturates() {
+        let policy = RetryPolicy { max_attempts: 100, base_delay_ms: 1000, max_retry_after_ms: 3_600_000 };
+        assert_eq!(policy.delay_ms(99), MAX_MESSAGE_TTL_MS);
+    }
+
+    #[test]
+    fn retry_policy_validate() {
+        assert!(RetryPolicy { max_attempts: 5, base_delay_ms: 5000, max_retry_after_ms: 3_600_000 }.validate().is_ok());
+        assert!(RetryPolicy { max_attempts: 0, base_delay_ms: 5000, max_retry_after_ms: 3_600_000 }.validate().is_err());
+        assert!(RetryPolicy { max_attempts: 5, base_delay_ms: 0, max_retry_after_ms: 3_600_000 }.validate().is_err());
+        assert!(RetryPolicy { max_attempts: 5, base_delay_ms: MAX_MESSAGE_TTL_MS + 1, max_retry_after_ms: 3_600_000 }.validate().is_err());
+    }
+
+    /// A Retry-After longer than the maximum is ignored
+    #[test]
+    fn retry_policy_retry_after() {
+        let policy = RetryPolicy { max_attempts: 5, base_delay_ms: 1000, max_retry_after_ms: 60_000 };
+        assert_eq!(policy.retry_after_ms(Some(30_000)), Some(30_000));
+        assert_eq!(policy.retry_after_ms(Some(60_000)), Some(60_000));
+        assert_eq!(policy.retry_after_ms(Some(86_400_000)), None);
+        assert_eq!(policy.retry_after_ms(None), None);
+    }
+
+    /// The first attempt is not a retry, and a policy of zero attempts has no retries
+    #[test]
+    fn retry_policy_retries() {
+        assert_eq!(RetryPolicy { max_attempts: 5, base_delay_ms: 1, max_retry_after_ms: 3_600_000 }.retries(), 4);
+        assert_eq!(RetryPolicy { max_attempts: 0, base_delay_ms: 1, max_retry_after_ms: 3_600_000 }.retries(), 0);
+    }
+
+    /// Messages without an attempts header have not been attempted
+    #[test]
+    fn get_attempts_no_header() {
+        assert_eq!(get_attempts(&BasicProperties::default()), 0);
+    }
+
+    /// The attempts header is read from the message headers
+    #[test]
+    fn get_attempts_with_header() {
+        let mut headers = FieldTable::default();
+        headers.insert(ATTEMPTS_HEADER.into(), AMQPValue::LongUInt(3));
+        let properties = BasicProperties::default().with_headers(headers);
+        assert_eq!(get_attempts(&properties), 3);
+    }
+
+    #[test]
+    fn retry_queue_names() {
+        assert_eq!(retry_queue_name("frontier", 5000), "frontier.retry.5000");
+        assert_eq!(retry_queue_name("frontier.3", 20000), "frontier.3.retry.20000");
+    }
+
+    #[test]
+    fn partitions_keys() {
+        assert_eq!(partitions_key("frontier"), "frontier:partitions");
+    }
+
+    #[test]
+    fn canonical_keys() {
+        assert_eq!(canonical_key("collection"), "collection:canonical");
+    }
+
+    #[test]
+    fn postponed_queue_names() {
+        assert_eq!(postponed_queue_name("frontier", 5000), "frontier.postponed.5000");
+    }
+
+    /// Tasks are postponed by the shortest delay that is at least their own, or at most a day
+    #[test]
+    fn postpone_delays() {
+        assert_eq!(postpone_delay_ms(0), 1_000);
+        assert_eq!(postpone_delay_ms(250), 1_000);
+        assert_eq!(postpone_delay_ms(2_000), 2_000);
+        assert_eq!(postpone_delay_ms(2_001), 5_000);
+        assert_eq!(postpone_delay_ms(100_000_000), 86_400_000);
+    }
+
+    /// A dead letter is read from the data and headers of a delivery
+    #[test]
+    fn dead_letter_from_delivery() {
+        let mut headers = FieldTable::default();
+        headers.insert(ERROR_KIND_HEADER.into(), AMQPValue::LongString("InvalidPage".into()));
+        headers.insert(ERROR_MESSAGE_HEADER.into(), AMQPValue::LongString("Could not read downloaded page".into()));
+        headers.insert(WORKER_HEADER.into(), AMQPValue::LongString("worker-123456".into()));
+        headers.insert(ATTEMPTS_HEADER.into(), AMQPValue::LongUInt(2));
+        headers.insert(TIMESTAMP_HEADER.into(), AMQPValue::LongLongInt(1573043200000));
+        let delivery = Delivery {
+            delivery_tag: 1,
+            exchange: "".into(),
+            routing_key: "dead-letter".into(),
+            redelivered: false,
+            properties: BasicProperties::default().with_headers(headers),
+            data: "http://aau.dk/".as_bytes().to_vec(),
+        };
+
+        let dead_letter = DeadLetter::from(&delivery);
+        assert_eq!(dead_letter.data, "http://aau.dk/".as_bytes().to_vec());
+        assert_eq!(dead_letter.error_kind, Some(String::from("InvalidPage")));
+        assert_eq!(dead_letter.error_message, Some(String::from("Could not read downloaded page")));
+        assert_eq!(dead_letter.worker, Some(String::from("worker-123456")));
+        assert_eq!(dead_letter.attempts, 2);
+        assert_eq!(dead_letter.timestamp, Some(1573043200000));
+    }
+
+    /// A dead letter without headers has unknown failure details
+    #[test]
+    fn dead_letter_from_delivery_no_headers() {
+        let delivery = Delivery {
+            delivery_tag: 1,
+            exchange: "".into(),
+            routing_key: "dead-letter".into(),
+            redelivered: false,
+            properties: BasicProperties::default(),
+            data: "http://aau.dk/".as_bytes().to_vec(),
+        };
+
+        let dead_letter = DeadLetter::from(&delivery);
+        assert_eq!(dead_letter.error_kind, None);
+        assert_eq!(dead_letter.attempts, 0);
+        assert_eq!(dead_letter.timestamp, None);
+    }
+
+    /// The priority of a task is capped to the maximum priority of the frontier
+    #[test]
+    fn task_properties_priority() {
+        let mut task = Task::new(Url::parse("http://aau.dk/").unwrap());
+        assert_eq!(*task_properties(&task, 10).priority(), None);
+
+        task.priority = Some(4);
+        assert_eq!(*task_properties(&task, 10).priority(), Some(4));
+        assert_eq!(*task_properties(&task, 2).priority(), Some(2));
+        assert_eq!(*task_properties(&task, 0).priority(), None);
+    }
+
+    /// The prefetch count is shared among the partitions, but every partition gets at least one
+    #[test]
+    fn partition_prefetch_counts() {
+        assert_eq!(partition_prefetch_count(20, 1), 20);
+        assert_eq!(partition_prefetch_count(20, 4), 5);
+        assert_eq!(partition_prefetch_count(5, 16), 1);
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::ops::DerefMut;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use redis::{Connection, RedisResult};
+use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use url::Url;
+
+use crate::credentials::{redact_message, redact_url};
+use crate::proxypool::ProxyPool;
+use crate::robots::rules::RobotsRules;
+use crate::task::Task;
+use crate::traits::Filter;
+
+/// Prefix of the Redis keys that hold the content of robots.txt files
+const ROBOTS_KEY_PREFIX: &str = "robots";
+/// Prefix of the Redis keys that hold the crawl delay of hosts in milliseconds
+pub(crate) const CRAWL_DELAY_KEY_PREFIX: &str = "crawl-delay";
+
+/// The key of the crawl delay of the given host
+pub(crate) fn crawl_delay_key(host: &str) -> String {
+    format!("{}:{}", CRAWL_DELAY_KEY_PREFIX, host)
+}
+
+/// The RobotsFilter is a Filter that removes tasks which urls are disallowed by the robots.txt
+/// of their host. The robots.txt files are downloaded once per host and cached in Redis, such
+/// that all workers in the cluster share them. The Crawl-delay of a host is stored in Redis as
+/// well, where the politeness handling will pick it up.
+pub(crate) struct RobotsFilter {
+    connection: Mutex<Connection>,
+    client: Client,
+    proxies: Option<Arc<ProxyPool>>,
+    user_agent: String,
+    ttl_secs: u64,
+}
+
+impl RobotsFilter {
+    /// Construct a new RobotsFilter following the rules for the given user agent. Cached
+    /// robots.txt files expire after `ttl_secs` seconds.
+    pub fn new(connection: Connection, user_agent: &str, ttl_secs: u64) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_str(user_agent).expect("Invalid user agent"));
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build robots.txt client");
+
+        RobotsFilter {
+            connection: Mutex::new(connection),
+            client,
+            proxies: None,
+            user_agent: user_agent.to_string(),
+            ttl_secs,
+        }
+    }
+
+    /// Construct a new RobotsFilter that downloads robots.txt files through the given pool of
+    /// proxies, if any, like the pages of the hosts
+    pub fn with_proxies(connection: Connection, user_agent: &str, ttl_secs: u64, proxies: Option<Arc<ProxyPool>>) -> Self {
+        RobotsFilter {
+            proxies,
+            ..RobotsFilter::new(connection, user_agent, ttl_secs)
+        }
+    }
+
+    /// Get the robots.txt rules of the origin of the given url, either from the cache or by
+    /// downloading the robots.txt file
+    fn get_rules(&self, url: &Url) -> RedisResult<RobotsRules> {
+        let key = format!("{}:{}", ROBOTS_KEY_PREFIX, url.origin().ascii_serialization());
+
+        let cached: Option<String> = {
+            let mut con = self.connection.lock().expect("Redis connection mutex was corrupted");
+            redis::cmd("GET").arg(key.as_str()).query(con.deref_mut())?
+        };
+        if let Some(content) = cached {
+            return Ok(RobotsRules::parse(&content, &self.user_agent));
+        }
+
+        let content = match self.download_robots(url) {
+            Some(content) => content,
+            // The robots.txt could not be retrieved at the moment, so allow everything without
+            // caching it, such that it is retried later
+            None => return Ok(RobotsRules::allow_all()),
+        };
+        let rules = RobotsRules::parse(&content, &self.user_agent);
+
+        let mut con = self.connection.lock().expect("Redis connection mutex was corrupted");
+        redis::cmd("SET")
+            .arg(key.as_str())
+            .arg(content.as_str())
+            .arg("EX")
+            .arg(self.ttl_secs)
+            .query::<()>(con.deref_mut())?;
+        if let (Some(host), Some(delay)) = (url.host_str(), rules.crawl_delay()) {
+            redis::cmd("SET")
+                .arg(crawl_delay_key(host))
+                .arg((delay * 1000.0) as u64)
+                .arg("EX")
+                .arg(self.ttl_secs)
+                .query::<()>(con.deref_mut())?;
+        }
+
+        Ok(rules)
+    }
+
+    /// Download the robots.txt of the origin of the given url. A missing robots.txt means that
+    /// everything is allowed, which is represented by an empty file. Returns None if the file
+    /// is temporarily unavailable.
+    fn download_robots(&self, url: &Url) -> Option<String> {
+        let robots_url = match url.join("/robots.txt") {
+            Ok(robots_url) => robots_url,
+            Err(_) => return Some(String::new()),
+        };
+
+        let (client, proxy) = match &self.proxies {
+            None => (&self.client, None),
+            Some(pool) => match pool.select(&robots_url) {
+                Some((index, client)) => (client, Some(index)),
+                None => {
+                    warn!("Could not download {}. Every proxy is out of rotation", redact_url(&robots_url));
+                    return None;
+                }
+            },
+        };
+        let result = client.get(robots_url.as_str()).send();
+        if let (Some(pool), Some(index)) = (&self.proxies, proxy) {
+            pool.report_response(index, &result);
+        }
+
+        match result {
+            Ok(mut res) => {
+                if res.status().is_success() {
+                    let mut content = String::new();
+                    match res.read_to_string(&mut content) {
+                        Ok(_) => Some(content),
+                        Err(e) => {
+                            warn!("Could not read {}. {}", redact_url(&robots_url), redact_message(&e.to_string(), &robots_url));
+                            None
+                        }
+                    }
+                } else if res.status().is_client_error() {
+                    Some(String::new())
+                } else {
+                    warn!("Could not download {}. Status {}", redact_url(&robots_url), res.status());
+                    None
+                }
+            }
+            Err(e) => {
+                warn!("Could not download {}. {}", redact_url(&robots_url), redact_message(&e.to_string(), &robots_url));
+                None
+            }
+        }
+    }
+}
+
+impl Filter for RobotsFilter {
+    /// Removes all tasks which url is disallowed by the robots.txt of its host
+    fn filter(&self, mut tasks: Vec<Task>) -> Vec<Task> {
+        // Many tasks share the same origin, so only look up the rules once per origin
+        let mut rules_by_origin: HashMap<String, RobotsRules> = HashMap::new();
+
+        tasks.drain(..).filter(|task| {
+            let origin = task.url.origin().ascii_serialization();
+            if !rules_by_origin.contains_key(&origin) {
+                let rules = match self.get_rules(&task.url) {
+                    Ok(rules) => rules,
+                    Err(e) => {
+                        // Rather visit the url than not crawling the host at all
+                        warn!("Failed to get robots.txt rules for {}. {}", origin, e);
+                        RobotsRules::allow_all()
+                    }
+                };
+                rules_by_origin.insert(origin.clone(), rules);
+            }
+
+            rules_by_origin[&origin].is_allowed(&task.url)
+        }).collect()
+    }
+}
@@ -0,0 +1,2 @@
+pub(crate) mod filter;
+pub(crate) mod rules;
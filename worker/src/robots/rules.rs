@@ -0,0 +1,291 @@
+use url::Url;
+
+/// A single Allow or Disallow rule of a robots.txt file
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+/// The RobotsRules are the rules of a robots.txt file that apply to a specific user agent.
+/// It follows the robots exclusion protocol as described by
+/// https://tools.ietf.org/html/draft-koster-rep-00 including the common Crawl-delay and
+/// Sitemap extensions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RobotsRules {
+    rules: Vec<Rule>,
+    crawl_delay: Option<f64>,
+    sitemaps: Vec<String>,
+}
+
+/// A group of a robots.txt file, i.e. a set of user agents and the rules that apply to them
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<f64>,
+}
+
+impl RobotsRules {
+    /// Rules that allow everything, e.g. used when a host has no robots.txt
+    pub fn allow_all() -> Self {
+        RobotsRules {
+            rules: vec![],
+            crawl_delay: None,
+            sitemaps: vec![],
+        }
+    }
+
+    /// Parse the content of a robots.txt file and keep the rules that apply to the given user
+    /// agent. The group with the most specific user agent matching ours is used, otherwise the
+    /// group of '*'.
+    pub fn parse(content: &str, user_agent: &str) -> Self {
+        // Only the product token of the user agent is matched, e.g. "DatScraper" of "DatScraper/0.1"
+        let product = user_agent.split('/').next().unwrap_or("").trim().to_lowercase();
+
+        let mut groups: Vec<Group> = vec![];
+        let mut sitemaps = vec![];
+        // Whether the last line was a user-agent line, in which case the next user-agent
+        // belongs to the same group
+        let mut in_user_agents = false;
+
+        for line in content.lines() {
+            // Remove comments
+            let line = match line.find('#') {
+                Some(index) => &line[..index],
+                None => line,
+            };
+            let (key, value) = match line.find(':') {
+                Some(index) => (line[..index].trim().to_lowercase(), line[index + 1..].trim()),
+                None => continue,
+            };
+
+            match key.as_str() {
+                "user-agent" => {
+                    if !in_user_agents || groups.is_empty() {
+                        groups.push(Group { user_agents: vec![], rules: vec![], crawl_delay: None });
+                    }
+                    groups.last_mut().unwrap().user_agents.push(value.to_lowercase());
+                    in_user_agents = true;
+                }
+                "allow" | "disallow" => {
+                    in_user_agents = false;
+                    // An empty disallow rule allows everything, which is the default
+                    if let (Some(group), false) = (groups.last_mut(), value.is_empty()) {
+                        group.rules.push(Rule { allow: key == "allow", pattern: value.to_string() });
+                    }
+                }
+                "crawl-delay" => {
+                    in_user_agents = false;
+                    if let (Some(group), Ok(delay)) = (groups.last_mut(), value.parse::<f64>()) {
+                        group.crawl_delay = Some(delay);
+                    }
+                }
+                // Sitemaps are not part of any group
+                "sitemap" => sitemaps.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        // Find the length of the most specific user agent matching ours
+        let best_match = groups.iter()
+            .flat_map(|group| group.user_agents.iter())
+            .filter(|agent| agent.as_str() != "*" && !product.is_empty() && product.contains(agent.as_str()))
+            .map(|agent| agent.len())
+            .max();
+
+        // Merge every group that matches, as the same user agent may appear in several groups
+        let mut rules = vec![];
+        let mut crawl_delay = None;
+        for group in groups.drain(..) {
+            let matches = group.user_agents.iter().any(|agent| match best_match {
+                Some(len) => agent.as_str() != "*" && agent.len() == len && product.contains(agent.as_str()),
+                None => agent.as_str() == "*",
+            });
+            if matches {
+                rules.extend(group.rules);
+                crawl_delay = crawl_delay.or(group.crawl_delay);
+            }
+        }
+
+        RobotsRules {
+            rules,
+            crawl_delay,
+            sitemaps,
+        }
+    }
+
+    /// Checks whether the given Url may be visited. The rule with the longest matching pattern
+    /// decides. If an Allow and a Disallow rule are equally long, the Allow rule wins.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        let decisive_rule = self.rules.iter()
+            .filter(|rule| pattern_matches(rule.pattern.as_str(), path.as_str()))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow));
+
+        match decisive_rule {
+            Some(rule) => rule.allow,
+            None => true,
+        }
+    }
+
+    /// The delay between visits in seconds requested by the robots.txt file, if any
+    pub fn crawl_delay(&self) -> Option<f64> {
+        self.crawl_delay
+    }
+
+    /// The sitemaps listed in the robots.txt file
+    pub fn sitemaps(&self) -> &Vec<String> {
+        &self.sitemaps
+    }
+}
+
+/// Checks whether a robots.txt path pattern matches the beginning of the given path. The pattern
+/// may contain '*', which matches any sequence of characters, and end with '$', which means
+/// the pattern must match the whole path.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let anchored = pattern.ends_with('$');
+    let pattern = pattern.trim_end_matches('$');
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+
+    // Match with backtracking to the last wildcard seen
+    let (mut p, mut s) = (0, 0);
+    let mut last_wildcard: Option<(usize, usize)> = None;
+    loop {
+        if p == pattern.len() && (!anchored || s == path.len()) {
+            return true;
+        }
+
+        if p < pattern.len() && pattern[p] == '*' {
+            last_wildcard = Some((p, s));
+            p += 1;
+        } else if p < pattern.len() && s < path.len() && pattern[p] == path[s] {
+            p += 1;
+            s += 1;
+        } else if let Some((wildcard_p, wildcard_s)) = last_wildcard {
+            // Let the last wildcard match one more character and try again
+            if wildcard_s >= path.len() {
+                return false;
+            }
+            last_wildcard = Some((wildcard_p, wildcard_s + 1));
+            p = wildcard_p + 1;
+            s = wildcard_s + 1;
+        } else {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::robots::rules::{pattern_matches, RobotsRules};
+
+    fn is_allowed(rules: &RobotsRules, url: &str) -> bool {
+        rules.is_allowed(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn pattern_matches_prefix() {
+        assert!(pattern_matches("/private", "/private"));
+        assert!(pattern_matches("/private", "/private/page.html"));
+        assert!(pattern_matches("/", "/anything"));
+        assert!(!pattern_matches("/private", "/public"));
+    }
+
+    #[test]
+    fn pattern_matches_wildcard() {
+        assert!(pattern_matches("/*.php", "/index.php"));
+        assert!(pattern_matches("/*.php", "/folder/index.php?id=1"));
+        assert!(pattern_matches("/a*b*c", "/axxbyyc"));
+        assert!(!pattern_matches("/*.php", "/index.html"));
+    }
+
+    #[test]
+    fn pattern_matches_end_anchor() {
+        assert!(pattern_matches("/*.pdf$", "/files/report.pdf"));
+        assert!(!pattern_matches("/*.pdf$", "/files/report.pdf?download=1"));
+        assert!(pattern_matches("/$", "/"));
+        assert!(!pattern_matches("/$", "/page"));
+    }
+
+    /// Test that the group of '*' is used when no group matches our user agent
+    #[test]
+    fn parse_wildcard_group() {
+        let content = "User-agent: *\nDisallow: /private\n\nUser-agent: OtherBot\nDisallow: /";
+        let rules = RobotsRules::parse(content, "DatScraper/0.1");
+
+        assert!(is_allowed(&rules, "http://aau.dk/"));
+        assert!(!is_allowed(&rules, "http://aau.dk/private/page"));
+    }
+
+    /// Test that the group of our user agent is preferred over the group of '*'
+    #[test]
+    fn parse_specific_group() {
+        let content = "User-agent: *\nDisallow: /\n\nUser-agent: datscraper\nDisallow: /private";
+        let rules = RobotsRules::parse(content, "DatScraper/0.1");
+
+        assert!(is_allowed(&rules, "http://aau.dk/"));
+        assert!(!is_allowed(&rules, "http://aau.dk/private"));
+    }
+
+    /// Test that consecutive user-agent lines share the rules that follow them
+    #[test]
+    fn parse_shared_group() {
+        let content = "User-agent: OtherBot\nUser-agent: DatScraper\nDisallow: /shared\n\nUser-agent: *\nDisallow: /";
+        let rules = RobotsRules::parse(content, "DatScraper");
+
+        assert!(is_allowed(&rules, "http://aau.dk/"));
+        assert!(!is_allowed(&rules, "http://aau.dk/shared"));
+    }
+
+    /// Test that the longest matching rule decides, and Allow wins ties
+    #[test]
+    fn longest_rule_decides() {
+        let content = "User-agent: *\nDisallow: /folder\nAllow: /folder/public\nDisallow: /page\nAllow: /page";
+        let rules = RobotsRules::parse(content, "DatScraper");
+
+        assert!(!is_allowed(&rules, "http://aau.dk/folder/private"));
+        assert!(is_allowed(&rules, "http://aau.dk/folder/public/index.html"));
+        assert!(is_allowed(&rules, "http://aau.dk/page"));
+    }
+
+    /// Test that comments, unknown lines and empty disallow rules are ignored
+    #[test]
+    fn parse_ignores_noise() {
+        let content = "# Our robots.txt\nUser-agent: * # everyone\nDisallow:\nFoo: bar\nnonsense\nDisallow: /tmp # temporary";
+        let rules = RobotsRules::parse(content, "DatScraper");
+
+        assert!(is_allowed(&rules, "http://aau.dk/"));
+        assert!(!is_allowed(&rules, "http://aau.dk/tmp/file"));
+    }
+
+    #[test]
+    fn parse_crawl_delay() {
+        let content = "User-agent: OtherBot\nCrawl-delay: 10\n\nUser-agent: *\nCrawl-delay: 2.5\nDisallow: /tmp";
+        let rules = RobotsRules::parse(content, "DatScraper");
+
+        assert_eq!(rules.crawl_delay(), Some(2.5));
+        assert_eq!(RobotsRules::parse("User-agent: *\nDisallow: /tmp", "DatScraper").crawl_delay(), None);
+    }
+
+    /// Test that sitemaps are found regardless of the groups they appear in
+    #[test]
+    fn parse_sitemaps() {
+        let content = "Sitemap: http://aau.dk/sitemap.xml\nUser-agent: OtherBot\nDisallow: /\nSitemap: http://aau.dk/news.xml";
+        let rules = RobotsRules::parse(content, "DatScraper");
+
+        assert_eq!(rules.sitemaps(), &vec![String::from("http://aau.dk/sitemap.xml"), String::from("http://aau.dk/news.xml")]);
+    }
+
+    #[test]
+    fn allow_all_allows_everything() {
+        assert!(is_allowed(&RobotsRules::allow_all(), "http://aau.dk/private"));
+        assert!(is_allowed(&RobotsRules::parse("", "DatScraper"), "http://aau.dk/private"));
+    }
+}
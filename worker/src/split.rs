@@ -1,3 +1,5 @@
+use url::Url;
+
 use crate::errors::ManagerResult;
 use crate::Task;
 use crate::traits::{Collection, Frontier, Manager, TaskProcessResult};
@@ -37,7 +39,7 @@ impl Manager for SplitManager {
     }
 
     /// Starts resolving tasks with the given resolve function
-    fn subscribe(&self, resolve_func: &dyn Fn(Task) -> TaskProcessResult) {
+    fn subscribe(&self, resolve_func: &(dyn Fn(Task) -> TaskProcessResult + Sync)) {
         self.frontier.subscribe(resolve_func)
     }
 
@@ -51,4 +53,9 @@ impl Manager for SplitManager {
     fn cull_known(&self, tasks: Vec<Task>) -> ManagerResult<Vec<Task>> {
         self.collection.cull_known(tasks)
     }
+
+    /// Records the canonical Url of a page in the Collection
+    fn record_canonical(&self, task: &Task, canonical: &Url) -> ManagerResult<bool> {
+        self.collection.record_canonical(task, canonical)
+    }
 }
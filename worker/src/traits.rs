@@ -1,69 +1,272 @@
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
 use url::Url;
 
 use crate::errors::{ArchiveResult, DownloadResult, ExtractResult, ManagerResult, NormaliseResult};
-use crate::task::Task;
+use crate::recrawl::validators::Validators;
+use crate::task::{SitemapInfo, Task};
 
-/// A Manager serves as the interface to the frontier and the collection
-pub trait Manager {
+/// A Manager serves as the interface to the frontier and the collection.
+/// The resolve function given to `subscribe` may be called concurrently from several threads.
+pub trait Manager: Sync {
     fn submit(&self, tasks: Vec<Task>) -> ManagerResult<()>;
 
-    fn subscribe(&self, resolve_func: &dyn Fn(Task) -> TaskProcessResult);
+    fn subscribe(&self, resolve_func: &(dyn Fn(Task) -> TaskProcessResult + Sync));
 
     fn close(self) -> ManagerResult<()>;
 
     fn cull_known(&self, tasks: Vec<Task>) -> ManagerResult<Vec<Task>>;
+
+    /// Record that the page of the task declares the given canonical Url, which is then known to
+    /// the collection. Returns whether the task is the first to declare it, i.e. whether the
+    /// content of the page is new rather than a duplicate of another page.
+    fn record_canonical(&self, task: &Task, canonical: &Url) -> ManagerResult<bool>;
 }
 
 /// A Frontier contains upcoming tasks
-pub trait Frontier {
+pub trait Frontier: Sync {
     fn submit(&self, task: Vec<Task>) -> ManagerResult<()>;
 
-    fn subscribe(&self, resolve_func: &dyn Fn(Task) -> TaskProcessResult);
+    fn subscribe(&self, resolve_func: &(dyn Fn(Task) -> TaskProcessResult + Sync));
 
     fn close(self: Box<Self>) -> ManagerResult<()>;
 }
 
-/// When resolving a task, there are three different outcomes:
+/// When resolving a task, there are four different outcomes:
 /// Ok: Task was completed
 /// Err: Task was erroneous and should be discarded.
 /// Reject: Task could not be completed this time. Let it be rescheduled.
+/// Postpone: Task cannot be started yet. Let it be rescheduled after the given number of
+/// milliseconds without counting it as an attempt.
 pub enum TaskProcessResult {
     Ok,
-    Err,
-    Reject,
+    Err(TaskFailure),
+    Reject(TaskFailure),
+    Postpone(u64),
+}
+
+/// Describes why a task could not be resolved, i.e. the kind of error and its message
+#[derive(Debug, Clone)]
+pub struct TaskFailure {
+    pub kind: String,
+    pub msg: String,
+    /// How long to wait before retrying the task, if the failure says so
+    pub retry_after_ms: Option<u64>,
 }
 
 /// A Collection contains every found task, which prevents work duplications
-pub trait Collection {
+pub trait Collection: Sync {
     fn cull_known(&self, tasks: Vec<Task>) -> ManagerResult<Vec<Task>>;
 
     fn submit(&self, tasks: Vec<Task>) -> ManagerResult<()>;
 
+    fn record_canonical(&self, task: &Task, canonical: &Url) -> ManagerResult<bool>;
+
     fn close(self: Box<Self>) -> ManagerResult<()>;
 }
 
 /// The Downloader downloads the page S associated with the given task
-pub trait Downloader<S> {
-    fn fetch_page(&self, task: &Task) -> DownloadResult<S>;
+pub trait Downloader<S>: Sync {
+    fn fetch_page(&self, task: &Task) -> DownloadResult<Response<S>>;
+}
+
+/// The Extractor extracts new links and target data D from the response with the page S
+pub trait Extractor<S, D>: Sync {
+    fn extract_content(&self, response: Response<S>) -> ExtractResult<(Vec<Link>, Vec<D>)>;
 }
 
-/// The Extractor extracts new Urls and target data D from the page S
-pub trait Extractor<S, D> {
-    fn extract_content(&self, page: S, url: &Url) -> ExtractResult<(Vec<Url>, Vec<D>)>;
+/// A Response is a page S downloaded by a Downloader along with what the host stated about it
+#[derive(Debug, Clone)]
+pub struct Response<S> {
+    /// The Url of the page after following redirects, which relative links are resolved against
+    pub url: Url,
+    pub status: u16,
+    /// The media type of the Content-Type header in lower case, e.g. "text/html"
+    pub content_type: Option<String>,
+    /// The charset parameter of the Content-Type header in lower case, e.g. "utf-8". Once the
+    /// page is decoded, it is the encoding that the page was decoded from.
+    pub charset: Option<String>,
+    pub headers: HeaderMap,
+    /// Time from sending the request until the headers of the response were received
+    pub response_time: Duration,
+    /// Time from sending the request until the entire page was received
+    pub download_time: Duration,
+    pub body: S,
 }
 
+impl<S> Response<S> {
+    /// Construct a new successful Response with the given page and nothing else known about it
+    pub fn new(url: Url, body: S) -> Self {
+        Response {
+            url,
+            status: 200,
+            content_type: None,
+            charset: None,
+            headers: HeaderMap::new(),
+            response_time: Duration::default(),
+            download_time: Duration::default(),
+            body,
+        }
+    }
+
+    /// Replace the page of this response, e.g. with a decompressed or decoded version of it
+    pub fn with_body<T>(self, body: T) -> Response<T> {
+        Response {
+            url: self.url,
+            status: self.status,
+            content_type: self.content_type,
+            charset: self.charset,
+            headers: self.headers,
+            response_time: self.response_time,
+            download_time: self.download_time,
+            body,
+        }
+    }
+}
+
+/// The kind of element of a page that a Link was found in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkSource {
+    /// The href of an <a>
+    Anchor,
+    /// The href of an <area> of an image map
+    Area,
+    /// The href of a <link> to another version of the page, i.e. rel=next, prev or alternate
+    Link,
+    /// The src of an <iframe>
+    Iframe,
+    /// The src of a <frame>
+    Frame,
+    /// The url of a <meta http-equiv=refresh>
+    MetaRefresh,
+    /// The urls of the srcset of an <img> or <source>
+    Srcset,
+    /// The action of a <form> that is submitted with GET
+    Form,
+    /// The canonical Url of the page, given by a <link rel=canonical> or a Link header. Every
+    /// page has at most one canonical link.
+    Canonical,
+}
+
+impl LinkSource {
+    /// Every source of links found in the elements of a page, which the HTMLLinkExtractor may
+    /// be configured with
+    pub const ALL: [LinkSource; 8] = [
+        LinkSource::Anchor,
+        LinkSource::Area,
+        LinkSource::Link,
+        LinkSource::Iframe,
+        LinkSource::Frame,
+        LinkSource::MetaRefresh,
+        LinkSource::Srcset,
+        LinkSource::Form,
+    ];
+
+    /// The name of the source, as given in configuration
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkSource::Anchor => "a",
+            LinkSource::Area => "area",
+            LinkSource::Link => "link",
+            LinkSource::Iframe => "iframe",
+            LinkSource::Frame => "frame",
+            LinkSource::MetaRefresh => "meta-refresh",
+            LinkSource::Srcset => "srcset",
+            LinkSource::Form => "form",
+            LinkSource::Canonical => "canonical",
+        }
+    }
+
+    /// The source of the given name, if any
+    pub fn from_name(name: &str) -> Option<LinkSource> {
+        LinkSource::ALL.iter().cloned().find(|source| source.name() == name)
+    }
+}
+
+/// A Link is a Url found by an Extractor along with what the page states about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub url: Url,
+    /// The text of the anchor, if the link is an anchor with text
+    pub text: Option<String>,
+    /// The kind of element the link was found in, if it was found in HTML
+    pub source: Option<LinkSource>,
+    /// Whether the page asks for the link not to be followed, i.e. rel=nofollow
+    pub nofollow: bool,
+    pub sitemap: Option<SitemapInfo>,
+    /// The priority of the task of following the link, if it has been decided
+    pub priority: Option<u8>,
+}
+
+impl Link {
+    /// Construct a new Link to the given Url with nothing else known about it
+    pub fn new(url: Url) -> Self {
+        Link {
+            url,
+            text: None,
+            source: None,
+            nofollow: false,
+            sitemap: None,
+            priority: None,
+        }
+    }
+
+    /// Fill in what is unknown about this link with what is known by another link to the same Url
+    pub fn merge(&mut self, other: Link) {
+        if self.text.is_none() {
+            self.text = other.text;
+        }
+        if self.source.is_none() {
+            self.source = other.source;
+        }
+        // The link may be followed if any page allows it
+        self.nofollow = self.nofollow && other.nofollow;
+        if self.sitemap.is_none() {
+            self.sitemap = other.sitemap;
+        }
+        if self.priority.is_none() {
+            self.priority = other.priority;
+        }
+    }
+
+    /// Construct the task of following this link, which was found when resolving the given task
+    pub fn into_task(self, parent: &Task) -> Task {
+        let mut task = parent.child(self.url);
+        task.sitemap = self.sitemap;
+        task.priority = self.priority;
+        task
+    }
+}
+
+
 /// The Filter selects which tasks to visit. When the `filter` method returns true, the task should
 /// be resolved.
-pub trait Filter {
+pub trait Filter: Sync {
     fn filter(&self, tasks: Vec<Task>) -> Vec<Task>;
 }
 
 /// The Archive stores the target data D
-pub trait Archive<D> {
+pub trait Archive<D>: Sync {
     fn archive_content(&self, content: Vec<D>) -> ArchiveResult<()>;
 }
 
-/// The Normaliser normalises URLs to avoid different Urls to the same page
-pub trait Normaliser {
-    fn normalise(&self, url: Vec<Url>) -> Vec<Url>;
+/// The Prioritiser decides the priority of following the links found when resolving a task, such
+/// that the frontier serves the most important tasks first. Priorities already decided by the
+/// Extractor are kept.
+pub trait Prioritiser: Sync {
+    fn prioritise(&self, parent: &Task, links: Vec<Link>) -> Vec<Link>;
+}
+
+/// The Scheduler keeps track of when pages were crawled and decides when each page is crawled
+/// again, given the hash of the content of the page when it was crawled. It also stores the
+/// validators of the page, which are sent when it is crawled again. The content hash and
+/// validators are None if the page was not modified since it was last crawled.
+pub trait Scheduler: Sync {
+    fn record_crawl(&self, task: &Task, content_hash: Option<u64>, validators: Option<&Validators>) -> ManagerResult<()>;
+}
+
+/// The Normaliser normalises the Urls of links to avoid different Urls to the same page
+pub trait Normaliser: Sync {
+    fn normalise(&self, links: Vec<Link>) -> Vec<Link>;
 }
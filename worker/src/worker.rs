@@ -1,15 +1,35 @@
 use std::collections::HashSet;
+use std::hash::Hash;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use url::Url;
 
+use crate::credentials::{redact_message, redact_url};
+use crate::errors::DownloadError;
+use crate::errors::DownloadErrorKind::{NotModified, Redirect, RejectedMediaType};
+use crate::recrawl::scheduler::content_hash;
+use crate::recrawl::validators::Validators;
 use crate::task::Task;
-use crate::traits::{Archive, Downloader, Extractor, Filter, Manager, Normaliser, TaskProcessResult};
+use crate::traits::{Archive, Downloader, Extractor, Filter, Manager, Normaliser, Prioritiser, Scheduler, TaskProcessResult, Link, LinkSource};
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::ops::Sub;
 use crate::metrics::influx_client::{InfluxClient, get_timestamp_millis, TimeSession, CountSession, write_task_url, write_task_error_url};
 use influx_db_client::{Points, Point, Value};
 
+/// The components a worker is constructed with
+pub struct WorkerComponents<S, D> {
+    pub manager: Box<dyn Manager>,
+    pub downloader: Box<dyn Downloader<S>>,
+    pub extractor: Box<dyn Extractor<S, D>>,
+    pub normaliser: Box<dyn Normaliser>,
+    pub prioritiser: Box<dyn Prioritiser>,
+    pub archive: Box<dyn Archive<D>>,
+    pub filter: Box<dyn Filter>,
+    pub robots: Box<dyn Filter>,
+    pub scheduler: Box<dyn Scheduler>,
+}
+
 /// A worker is the web crawler module that resolves tasks. The components of the worker
 /// define every aspect of the workers behaviour.
 pub struct Worker<S, D> {
@@ -18,8 +38,12 @@ pub struct Worker<S, D> {
     downloader: Box<dyn Downloader<S>>,
     extractor: Box<dyn Extractor<S, D>>,
     normaliser: Box<dyn Normaliser>,
+    prioritiser: Box<dyn Prioritiser>,
     archive: Box<dyn Archive<D>>,
     filter: Box<dyn Filter>,
+    robots: Box<dyn Filter>,
+    scheduler: Box<dyn Scheduler>,
+    max_depth: Option<u32>,
 
     // Phantom data markers are used to please the type checker about S and D.
     // Without it will believe that S and D are unused even though the determine the
@@ -28,25 +52,33 @@ pub struct Worker<S, D> {
     _data_type_marker: PhantomData<D>,
 }
 
-impl<S, D> Worker<S, D> {
-    /// Create a new worker with the given components.
-    pub fn new(
-        name: &str,
-        manager: Box<dyn Manager>,
-        downloader: Box<dyn Downloader<S>>,
-        extractor: Box<dyn Extractor<S, D>>,
-        normaliser: Box<dyn Normaliser>,
-        archive: Box<dyn Archive<D>>,
-        filter: Box<dyn Filter>,
-    ) -> Self {
+impl<S: Sync + Hash, D: Sync> Worker<S, D> {
+    /// Create a new worker with the given components. Links found on pages at `max_depth`
+    /// are not followed.
+    pub fn new(name: &str, components: WorkerComponents<S, D>, max_depth: Option<u32>) -> Self {
+        let WorkerComponents {
+            manager,
+            downloader,
+            extractor,
+            normaliser,
+            prioritiser,
+            archive,
+            filter,
+            robots,
+            scheduler,
+        } = components;
         Worker {
             name: String::from(name),
             manager,
             downloader,
             extractor,
             normaliser,
+            prioritiser,
             archive,
             filter,
+            robots,
+            scheduler,
+            max_depth,
             _page_type_marker: PhantomData,
             _data_type_marker: PhantomData,
         }
@@ -54,57 +86,160 @@ impl<S, D> Worker<S, D> {
 
     /// Starts the worker. It will now listen to the manager for new tasks are resolve those.
     /// Resolving includes downloading, extracting, archiving, and submitting new tasks.
+    /// Depending on the manager, several tasks may be resolved concurrently.
     /// This is a blocking operation.
-    pub fn start(&self, influxdb_client: Option<InfluxClient>) {
+    pub fn start(&self, influxdb_client: Option<Arc<InfluxClient>>) {
         info!("Worker {} has started", self.name);
         self.manager.subscribe(&|task| {
             let mut time_session = TimeSession::new("worker_processing_time", &self.name);
             let mut count_session = CountSession::new("worker_processing_count", &self.name);
+            time_session.add_tag("depth", &task.depth.to_string());
+            count_session.add_tag("depth", &task.depth.to_string());
 
-            info!("Worker {} received task {}", self.name, task.url);
+            info!("Worker {} received task {}", self.name, redact_url(&task.url));
             if let Some(client) = &influxdb_client {
-                write_task_url(task.url.as_str(), "worker_duplicate_task", &self.name, client);
+                write_task_url(&task.url, task.depth, "worker_duplicate_task", &self.name, client);
             }
             time_session.add_time_field("receive_task_time");
 
             match self.downloader.fetch_page(&task) {
+                Err(DownloadError { kind: NotModified, .. }) => {
+                    // The page is unchanged since the last crawl, so there is nothing new to
+                    // extract or archive
+                    debug!("{} found task {} not modified", self.name, redact_url(&task.url));
+                    time_session.add_time_field("download_task_time");
+                    self.record_crawl(&task, None, None);
+                    count_session.add_final_count_field("not_modified", 1);
+                    if let Some(client) = &influxdb_client {
+                        time_session.write_point(client);
+                        count_session.write_point(client);
+                    }
+                    TaskProcessResult::Ok
+                }
+                Err(DownloadError { kind: RejectedMediaType(media_type), .. }) => {
+                    // The page is of no interest, e.g. an image or an archive, so it is skipped
+                    debug!("{} skipped task {} of media type {}", self.name, redact_url(&task.url), media_type);
+                    time_session.add_time_field("download_task_time");
+                    count_session.add_final_count_field("rejected_media_type", 1);
+                    if let Some(client) = &influxdb_client {
+                        write_task_error_url(&task.url,
+                                             task.depth,
+                                             "worker_rejected_task",
+                                             "RejectedMediaType",
+                                             None,
+                                             &self.name,
+                                             client);
+                        time_session.write_point(client);
+                        count_session.write_point(client);
+                    }
+                    TaskProcessResult::Ok
+                }
+                Err(DownloadError { kind: Redirect(status, Some(location)), .. }) => {
+                    // The page has moved, so the new location is followed like any other link
+                    debug!("{} found task {} redirected to {}", self.name, redact_url(&task.url), location);
+                    time_session.add_time_field("download_task_time");
+                    if let Some(client) = &influxdb_client {
+                        write_task_error_url(&task.url,
+                                             task.depth,
+                                             "worker_redirect_task",
+                                             "Redirect",
+                                             Some(status),
+                                             &self.name,
+                                             client);
+                    }
+                    let url = match task.url.join(&location) {
+                        Ok(url) => url,
+                        Err(e) => {
+                            warn!("{} found an invalid redirect from {} to {}. {}", self.name, redact_url(&task.url), location, e);
+                            return TaskProcessResult::Ok;
+                        }
+                    };
+
+                    let tasks: Vec<Task> = self.normaliser.normalise(vec![Link::new(url)])
+                        .drain(..)
+                        .map(|link| link.into_task(&task))
+                        .collect();
+                    let result = self.manager.cull_known(self.filter.filter(tasks))
+                        .and_then(|new_tasks| self.manager.submit(self.robots.filter(new_tasks)));
+                    if let Err(e) = result {
+                        error!("{} failed submitting the redirect of {}. {}", self.name, redact_url(&task.url), e);
+                        return TaskProcessResult::from(e);
+                    }
+                    time_session.add_time_field("submit_task_time");
+                    count_session.add_final_count_field("redirected", 1);
+
+                    if let Some(client) = &influxdb_client {
+                        time_session.write_point(client);
+                        count_session.write_point(client);
+                    }
+                    TaskProcessResult::Ok
+                }
                 Err(e) => {
-                    error!("{} failed to download a page. {}", self.name, e);
+                    error!("{} failed to download a page. {}", self.name, redact_message(&e.to_string(), &task.url));
                     if let Some(client) = &influxdb_client {
-                        write_task_error_url(task.url.as_str(),
+                        write_task_error_url(&task.url,
+                                             task.depth,
                                              "worker_error_task",
-                                             &format!("{:?}", e.kind),
+                                             e.kind.name(),
+                                             e.kind.status(),
                                              &self.name,
                                              client);
                     }
                     return TaskProcessResult::from(e);
                 }
-                Ok(page) => {
+                Ok(response) => {
                     time_session.add_time_field("download_task_time");
+                    if response.url != task.url {
+                        debug!("{} followed redirects from {} to {}", self.name, redact_url(&task.url), redact_url(&response.url));
+                    }
+                    let page_hash = content_hash(&response.body);
+                    let validators = Validators::from_headers(&response.headers);
 
-                    match self.extractor.extract_content(page, &task.url) {
+                    match self.extractor.extract_content(response) {
                         Err(e) => {
                             error!("{} failed to extract data from page. {}", self.name, e);
                             if let Some(client) = &influxdb_client {
-                                write_task_error_url(task.url.as_str(),
+                                write_task_error_url(&task.url,
+                                                     task.depth,
                                                      "worker_error_task",
                                                      &format!("{:?}", e.kind),
+                                                     None,
                                                      &self.name,
                                                      client);
                             }
                             return TaskProcessResult::from(e);
                         }
-                        Ok((mut urls, data)) => {
+                        Ok((links, data)) => {
                             time_session.add_time_field("extract_task_time");
-                            count_session.add_first_count_field("extracted_links", urls.len() as i64);
+                            count_session.add_first_count_field("extracted_links", links.len() as i64);
+
+                            // Pages whose canonical Url was declared by another page first are
+                            // duplicates, so their data is not archived again
+                            let data = if self.is_duplicate(&task, &links) {
+                                debug!("{} found task {} to be a duplicate of its canonical url", self.name, redact_url(&task.url));
+                                if let Some(client) = &influxdb_client {
+                                    write_task_error_url(&task.url,
+                                                         task.depth,
+                                                         "worker_canonical_duplicate_task",
+                                                         "CanonicalDuplicate",
+                                                         None,
+                                                         &self.name,
+                                                         client);
+                                }
+                                vec![]
+                            } else {
+                                data
+                            };
 
                             // Archiving
                             if let Err(e) = self.archive.archive_content(data) {
                                 error!("{} failed archiving some data. {}", self.name, e);
                                 if let Some(client) = &influxdb_client {
-                                    write_task_error_url(task.url.as_str(),
+                                    write_task_error_url(&task.url,
+                                                         task.depth,
                                                          "worker_error_task",
                                                          &format!("{:?}", e.kind),
+                                                         None,
                                                          &self.name,
                                                          client);
                                 }
@@ -112,13 +247,32 @@ impl<S, D> Worker<S, D> {
                             }
                             time_session.add_time_field("archive_task_time");
 
+                            // Do not follow links beyond the maximum depth
+                            if let Some(max_depth) = self.max_depth {
+                                if task.depth >= max_depth {
+                                    debug!("{} reached the maximum depth at task {}", self.name, redact_url(&task.url));
+                                    self.record_crawl(&task, Some(page_hash), Some(&validators));
+                                    time_session.add_time_field("schedule_task_time");
+                                    count_session.add_final_count_field("submitted_links", 0);
+                                    if let Some(client) = &influxdb_client {
+                                        time_session.write_point(client);
+                                        count_session.write_point(client);
+                                    }
+                                    return TaskProcessResult::Ok;
+                                }
+                            }
+
                             // Normalising urls
-                            let tasks: Vec<Task> = self.normaliser.normalise(urls)
+                            let links = self.normaliser.normalise(links);
+                            time_session.add_time_field("normalise_task_time");
+                            count_session.add_count_field("normalised_links", links.len() as i64);
+
+                            // Prioritising links, such that the most important tasks are resolved first
+                            let tasks: Vec<Task> = self.prioritiser.prioritise(&task, links)
                                 .drain(..)
-                                .map(|url| Task { url })
+                                .map(|link| link.into_task(&task))
                                 .collect();
-                            time_session.add_time_field("normalise_task_time");
-                            count_session.add_count_field("normalised_links", tasks.len() as i64);
+                            time_session.add_time_field("prioritise_task_time");
 
                             let filtered_tasks = self.filter.filter(tasks);
 
@@ -130,15 +284,24 @@ impl<S, D> Worker<S, D> {
                                 Ok(new_tasks) => {
                                     time_session.add_time_field("culling_task_time");
                                     count_session.add_count_field("culled_links", new_tasks.len() as i64);
-                                    count_session.add_final_count_field("submitted_links", new_tasks.len() as i64);
+
+                                    // Drop new tasks that are disallowed by the robots.txt of their host. Known
+                                    // tasks are culled first, such that robots.txt is only checked for new ones
+                                    let new_count = new_tasks.len();
+                                    let allowed_tasks = self.robots.filter(new_tasks);
+                                    time_session.add_time_field("robots_task_time");
+                                    count_session.add_count_field("robots_links", (new_count - allowed_tasks.len()) as i64);
+                                    count_session.add_final_count_field("submitted_links", allowed_tasks.len() as i64);
 
 
-                                    if let Err(e) = self.manager.submit(new_tasks) {
+                                    if let Err(e) = self.manager.submit(allowed_tasks) {
                                         error!("{} failed submitting new tasks to the manager. {}", self.name, e);
                                         if let Some(client) = &influxdb_client {
-                                            write_task_error_url(task.url.as_str(),
+                                            write_task_error_url(&task.url,
+                                                                 task.depth,
                                                                  "worker_error_task",
                                                                  &format!("{:?}", e.kind),
+                                                                 None,
                                                                  &self.name,
                                                                  client);
                                         }
@@ -148,9 +311,11 @@ impl<S, D> Worker<S, D> {
                                 Err(e) => {
                                     error!("{} failed to check if tasks are present in the collection. {}", self.name, e);
                                     if let Some(client) = &influxdb_client {
-                                        write_task_error_url(task.url.as_str(),
+                                        write_task_error_url(&task.url,
+                                                             task.depth,
                                                              "worker_error_task",
                                                              &format!("{:?}", e.kind),
+                                                             None,
                                                              &self.name,
                                                              client);
                                     }
@@ -158,6 +323,10 @@ impl<S, D> Worker<S, D> {
                                 }
                             }
 
+                            // Schedule when to crawl the page again, now that the task has succeeded
+                            self.record_crawl(&task, Some(page_hash), Some(&validators));
+                            time_session.add_time_field("schedule_task_time");
+
                             if let Some(client) = &influxdb_client {
                                 time_session.write_point(client);
                                 count_session.write_point(client);
@@ -170,4 +339,35 @@ impl<S, D> Worker<S, D> {
             }
         });
     }
-}
\ No newline at end of file
+
+    /// Record that the page of the task was crawled, such that it is crawled again later. The
+    /// task succeeded even if this fails, so it is not retried.
+    fn record_crawl(&self, task: &Task, content_hash: Option<u64>, validators: Option<&Validators>) {
+        if let Err(e) = self.scheduler.record_crawl(task, content_hash, validators) {
+            error!("{} failed to schedule a recrawl of {}. {}", self.name, redact_url(&task.url), e);
+        }
+    }
+
+    /// Whether the page of the task is a duplicate, i.e. whether it declares a canonical Url that
+    /// another task declared first. The canonical Url is normalised like any other link and
+    /// recorded by the manager. Pages without a canonical Url are never duplicates.
+    fn is_duplicate(&self, task: &Task, links: &[Link]) -> bool {
+        let canonical = match links.iter().find(|link| link.source == Some(LinkSource::Canonical)) {
+            Some(link) => link.clone(),
+            None => return false,
+        };
+        let canonical = match self.normaliser.normalise(vec![canonical]).pop() {
+            Some(link) => link.url,
+            None => return false,
+        };
+
+        match self.manager.record_canonical(task, &canonical) {
+            Ok(first) => !first,
+            Err(e) => {
+                // Rather archive a duplicate than lose a page
+                error!("{} failed to record the canonical url of {}. {}", self.name, redact_url(&task.url), e);
+                false
+            }
+        }
+    }
+}